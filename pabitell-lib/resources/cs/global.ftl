@@ -8,6 +8,8 @@ last_stories = Poslední příběhy
 no_camera = Vybraná kamera není dostupná
 built_on = Sestaveno
 will_join_the_game = {$characterName} se připojí ke hře.
+spectator = Divák
+will_spectate_the_game = Zařízení bude příběh pouze sledovat.
 not_json = Nahraný soubor není v JSON formátu.
 wrong_json = Formát nahraného souboru není platný.
 world_editor = Editor příběhu
@@ -16,3 +18,16 @@ navigate = Naviguj
 join_story = Připojit se k příběhu
 use_item = Použij věc
 move_pick_or_use = Přesunout se, zvednout nebo použít
+error = Chyba
+error_unknown_world = Příběh nebyl nalezen.
+error_event_not_available = Akci teď nelze provést.
+error_parse_error = Zprávě se nepodařilo porozumět.
+error_version_mismatch = Příběh byl uložen v nekompatibilní verzi.
+error_not_owner = Zařízení není vlastníkem příběhu.
+error_no_candidate = Není připojené žádné jiné zařízení, které by příběh převzalo.
+error_access_denied = Vaše role v příběhu toto neumožňuje.
+error_invalid_message = Server zprávu odmítl.
+error_timeout = Nikdo neodpověděl včas.
+owner = Vlastník
+ownership_transferred = Příběh byl předán jinému zařízení.
+ownership_elected = Toto zařízení je nyní vlastníkem příběhu.
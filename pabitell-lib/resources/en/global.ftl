@@ -8,6 +8,8 @@ last_stories = Last stories
 no_camera = Selected camera is not available
 built_on = Built on
 will_join_the_game = {$characterName} will join the game.
+spectator = Spectator
+will_spectate_the_game = The device will only watch the story.
 not_json = The provided file is not in JSON format.
 wrong_json = The format of the provided file is not valid.
 world_editor = World editor
@@ -16,3 +18,16 @@ navigate = Navigate
 join_story = Join story
 use_item = Use item
 move_pick_or_use = Move, pick or use
+error = Error
+error_unknown_world = The story was not found.
+error_event_not_available = The action can't be performed right now.
+error_parse_error = The message could not be understood.
+error_version_mismatch = The story was saved in an incompatible version.
+error_not_owner = The device is not the owner of the story.
+error_no_candidate = No other device is connected to take over the story.
+error_access_denied = Your role in the story doesn't allow this.
+error_invalid_message = The message was refused by the server.
+error_timeout = Nobody responded in time.
+owner = Owner
+ownership_transferred = The story was handed over to other device.
+ownership_elected = This device is now the owner of the story.
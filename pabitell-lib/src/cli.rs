@@ -1,14 +1,19 @@
 pub mod backend;
 pub mod cmdline;
+pub mod commands;
+pub mod parser;
+mod plain;
+pub mod remote;
+pub mod transcript;
 
 use anyhow::{anyhow, Result};
 use skim::prelude::*;
 use sled::Db;
-use std::io::prelude::*;
+use std::{io::prelude::*, path::PathBuf};
 use term::color::{self, Color};
 use uuid::Uuid;
 
-use crate::{Narrator, World};
+use crate::{protocol, Event, Narrator, World};
 
 #[derive(Clone)]
 pub struct PabitellItem {
@@ -27,6 +32,18 @@ where
     t.reset().unwrap();
 }
 
+/// Prints the message (without colors in the plain frontend)
+fn notice<S>(options: &CliOptions, text_color: Color, text: S)
+where
+    S: std::fmt::Display,
+{
+    if options.plain {
+        println!("{text}");
+    } else {
+        println(text_color, text);
+    }
+}
+
 impl SkimItem for PabitellItem {
     fn text(&self) -> Cow<str> {
         Cow::Borrowed(&self.code)
@@ -72,6 +89,9 @@ enum View {
     Reset,
     Load,
     Delete,
+    History,
+    Checkout,
+    Diagnose,
     Back,
     Exit,
 }
@@ -89,6 +109,9 @@ impl SkimItem for View {
             Self::Reset => Cow::Borrowed("reset"),
             Self::Load => Cow::Borrowed("load"),
             Self::Delete => Cow::Borrowed("delete"),
+            Self::History => Cow::Borrowed("history"),
+            Self::Checkout => Cow::Borrowed("checkout"),
+            Self::Diagnose => Cow::Borrowed("diagnose"),
             Self::Back => Cow::Borrowed("back"),
         }
     }
@@ -254,9 +277,87 @@ impl SkimItem for EventItem {
     }
 }
 
-fn select_event(world: &dyn World, narrator: &dyn Narrator) -> Option<Vec<EventItem>> {
-    let events = narrator
+/// Events which can be triggered (only by the character if set)
+fn available_events(
+    world: &dyn World,
+    narrator: &dyn Narrator,
+    character: Option<&str>,
+) -> Vec<Box<dyn Event>> {
+    narrator
         .available_events_sorted(world)
+        .into_iter()
+        .filter(|e| character.map(|c| e.initiator() == c).unwrap_or(true))
+        .collect()
+}
+
+/// Events which can't be triggered (including the ones of other characters when `character` is not set)
+fn unavailable_events(
+    world: &dyn World,
+    narrator: &dyn Narrator,
+    character: Option<&str>,
+) -> Vec<Box<dyn Event>> {
+    let mut events: Vec<Box<dyn Event>> = narrator
+        .all_events(world)
+        .into_iter()
+        .filter(|e| character.map(|c| e.initiator() == c).unwrap_or(true))
+        .filter(|e| !e.can_be_triggered(world))
+        .collect();
+    events.sort_by_key(|e| e.sort_key(world));
+    events
+}
+
+#[derive(Clone)]
+struct DiagnosisItem {
+    action: String,
+    diagnosis: String,
+}
+
+impl SkimItem for DiagnosisItem {
+    fn text(&self) -> Cow<str> {
+        Cow::Borrowed(&self.action)
+    }
+
+    fn display<'a>(&'a self, _context: DisplayContext<'a>) -> AnsiString<'a> {
+        AnsiString::new_string(self.action.clone(), vec![])
+    }
+    fn preview(&self, _context: PreviewContext) -> ItemPreview {
+        ItemPreview::AnsiText(self.diagnosis.clone())
+    }
+}
+
+/// Shows why the events can't be triggered
+fn select_diagnosis(
+    world: &dyn World,
+    narrator: &dyn Narrator,
+    character: Option<&str>,
+) -> Option<DiagnosisItem> {
+    let options = SkimOptionsBuilder::default()
+        .height(Some("50%"))
+        .preview(Some(""))
+        .build()
+        .unwrap();
+
+    let (tx_item, rx_item): (SkimItemSender, SkimItemReceiver) = unbounded();
+    for event in unavailable_events(world, narrator, character) {
+        let _ = tx_item.send(Arc::new(DiagnosisItem {
+            action: event.action_text(world),
+            diagnosis: event.get_condition().diagnose(world).to_string(),
+        }));
+    }
+    drop(tx_item); // so that skim could know when to stop waiting for more items.
+
+    let selected_items = Skim::run_with(&options, Some(rx_item)).map(|out| out.selected_items)?;
+    selected_items
+        .first()
+        .and_then(|e| (*e).as_any().downcast_ref::<DiagnosisItem>().cloned())
+}
+
+fn select_event(
+    world: &dyn World,
+    narrator: &dyn Narrator,
+    character: Option<&str>,
+) -> Option<Vec<EventItem>> {
+    let events = available_events(world, narrator, character)
         .iter()
         .enumerate()
         .map(|(idx, e)| EventItem {
@@ -295,7 +396,14 @@ fn controls_menu() -> Option<View> {
         .unwrap();
 
     let (tx_item, rx_item): (SkimItemSender, SkimItemReceiver) = unbounded();
-    for item in [View::Reset, View::Load, View::Delete, View::Back] {
+    for item in [
+        View::Reset,
+        View::Load,
+        View::Delete,
+        View::History,
+        View::Diagnose,
+        View::Back,
+    ] {
         let _ = tx_item.send(Arc::new(item));
     }
     drop(tx_item); // so that skim could know when to stop waiting for more items.
@@ -359,21 +467,241 @@ fn select_stored_world(db: &Db, story: &str) -> Result<Option<Uuid>> {
     }
 }
 
-pub fn start_cli_app<W, N, S>(db_path: &str, story: S, mut world: W, narrator: N) -> Result<()>
-where
-    W: World,
-    N: Narrator,
-    S: ToString,
-{
-    let story = story.to_string();
-
-    let lang = select_language(
-        world
-            .available_languages()
-            .iter()
+fn format_changes(changes: &[backend::Change]) -> String {
+    let value = |value: &Option<serde_json::Value>| {
+        value
+            .as_ref()
             .map(|e| e.to_string())
-            .collect(),
-    )
+            .unwrap_or_else(|| "-".to_string())
+    };
+    changes
+        .iter()
+        .map(|e| format!("{}: {} -> {}", e.path, value(&e.old), value(&e.new)))
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+#[derive(Clone)]
+struct SnapshotItem {
+    event_count: usize,
+    action: String,
+    /// Changes between the snapshot and the current world
+    changes: String,
+}
+
+impl SkimItem for SnapshotItem {
+    fn text(&self) -> Cow<str> {
+        Cow::Owned(format!("{}. {}", self.event_count, self.action))
+    }
+
+    fn display<'a>(&'a self, _context: DisplayContext<'a>) -> AnsiString<'a> {
+        AnsiString::new_string(format!("{}. {}", self.event_count, self.action), vec![])
+    }
+    fn preview(&self, _context: PreviewContext) -> ItemPreview {
+        ItemPreview::AnsiText(self.changes.clone())
+    }
+}
+
+fn select_snapshot(
+    db: &Db,
+    story: &str,
+    world: &dyn World,
+    narrator: &dyn Narrator,
+) -> Result<Option<backend::Snapshot>> {
+    let options = SkimOptionsBuilder::default()
+        .height(Some("50%"))
+        .preview(Some(""))
+        .build()
+        .unwrap();
+
+    let mut snapshots = backend::history(db, story, world.id())?;
+    let current = world.dump();
+    let (tx_item, rx_item): (SkimItemSender, SkimItemReceiver) = unbounded();
+    // latest first
+    for snapshot in snapshots.iter().rev() {
+        let action = snapshot
+            .event
+            .clone()
+            .and_then(|e| narrator.parse_event(world, e))
+            .map(|e| e.action_text(world))
+            .unwrap_or_else(|| "initial state".to_string());
+        let _ = tx_item.send(Arc::new(SnapshotItem {
+            event_count: snapshot.event_count,
+            action,
+            changes: format_changes(&backend::diff(&current, &snapshot.world)),
+        }));
+    }
+    drop(tx_item); // so that skim could know when to stop waiting for more items.
+
+    let selected_items = Skim::run_with(&options, Some(rx_item))
+        .map(|out| out.selected_items)
+        .unwrap_or_default();
+    let event_count = if let Some(item) = selected_items.first() {
+        (*item)
+            .as_any()
+            .downcast_ref::<SnapshotItem>()
+            .unwrap()
+            .event_count
+    } else {
+        return Ok(None);
+    };
+    Ok(snapshots
+        .iter()
+        .position(|e| e.event_count == event_count)
+        .map(|idx| snapshots.swap_remove(idx)))
+}
+
+fn history_menu() -> Option<View> {
+    let options = SkimOptionsBuilder::default()
+        .height(Some("50%"))
+        .build()
+        .unwrap();
+
+    let (tx_item, rx_item): (SkimItemSender, SkimItemReceiver) = unbounded();
+    for item in [View::Checkout, View::Back] {
+        let _ = tx_item.send(Arc::new(item));
+    }
+    drop(tx_item); // so that skim could know when to stop waiting for more items.
+
+    let selected_items = Skim::run_with(&options, Some(rx_item)).map(|out| out.selected_items)?;
+    if selected_items.is_empty() {
+        None
+    } else {
+        Some(*(*selected_items[0]).as_any().downcast_ref::<View>()?)
+    }
+}
+
+/// Options of the interactive app
+#[derive(Debug, Clone, Default)]
+pub struct CliOptions {
+    /// Transcript file where the triggered events are recorded
+    pub record: Option<PathBuf>,
+    /// Use the line based frontend instead of skim
+    pub plain: bool,
+    /// Match unknown commands of the plain frontend against the available events
+    pub parser: bool,
+}
+
+/// Where the world is kept
+enum Session {
+    /// World stored in the local database
+    Local(Db),
+    /// World owned by another device connected to the server
+    Remote(remote::Connection),
+}
+
+/// Outcome of an event triggered from a frontend
+enum Triggered {
+    /// Success text of the event
+    Success(String),
+    /// Fail text of the event or the reason why it couldn't be sent
+    Failed(String),
+    /// Event was sent to the owner (its text arrives with the notification)
+    Sent,
+}
+
+/// Triggers the event in the session and stores or records the world
+fn trigger_event(
+    session: &mut Session,
+    story: &str,
+    world: &mut dyn World,
+    event: &mut dyn Event,
+    recorder: Option<&mut transcript::Recorder>,
+) -> Result<Triggered> {
+    match session {
+        Session::Local(db) => {
+            let success = event.can_be_triggered(world);
+            let res = if success {
+                Triggered::Success(format!(
+                    "{}. {}",
+                    world.event_count() + 1,
+                    event.success_text(world)
+                ))
+            } else {
+                Triggered::Failed(event.fail_text(world))
+            };
+            event.trigger(world);
+            backend::store_event(db, story, world, event)?;
+            if let Some(recorder) = recorder {
+                recorder.event(event, success, world)?;
+            }
+            Ok(res)
+        }
+        // success text is printed when the notification from the owner arrives
+        Session::Remote(connection) => match connection.trigger_event(event.dump()) {
+            Ok(true) => Ok(Triggered::Sent),
+            Ok(false) => Ok(Triggered::Failed(event.fail_text(world))),
+            Err(err) => Ok(Triggered::Failed(format!(
+                "Failed to trigger event: {}",
+                err
+            ))),
+        },
+    }
+}
+
+/// Applies notifications from the server and refreshes the world when needed
+///
+/// messages are passed to `show`
+fn sync_remote(
+    connection: &mut remote::Connection,
+    world: &mut dyn World,
+    narrator: &dyn Narrator,
+    recorder: Option<&mut transcript::Recorder>,
+    show: &mut dyn FnMut(Color, String),
+) -> Result<()> {
+    let mut refresh = false;
+    let mut recorder = recorder;
+    for notification in connection.notifications()? {
+        match notification {
+            protocol::NotificationMessage::Event(notification) => {
+                if let Some(recorder) = recorder.as_mut() {
+                    recorder.remote_event(notification.event.clone(), notification.event_count)?;
+                }
+                if let Some(event) = narrator.parse_event(world, notification.event) {
+                    show(
+                        color::BRIGHT_CYAN,
+                        format!(
+                            "{}. {}",
+                            notification.event_count,
+                            event.success_text(world)
+                        ),
+                    );
+                }
+                refresh = true;
+            }
+            protocol::NotificationMessage::WorldUpdate => refresh = true,
+            protocol::NotificationMessage::Joined(joined) => {
+                show(
+                    color::BRIGHT_MAGENTA,
+                    format!("Character '{}' joined", joined.character["name"]),
+                );
+            }
+            protocol::NotificationMessage::Elected(_) => {
+                show(
+                    color::BRIGHT_RED,
+                    "Owner left the world and it can't be owned by the CLI".to_string(),
+                );
+            }
+        }
+    }
+    if refresh {
+        world.load(connection.get_world()?)?;
+    }
+    Ok(())
+}
+
+/// Lets the user pick the language and sets up a new world
+fn setup_world(world: &mut dyn World, options: &CliOptions) -> Result<()> {
+    let languages: Vec<String> = world
+        .available_languages()
+        .iter()
+        .map(|e| e.to_string())
+        .collect();
+    let lang = if options.plain {
+        plain::select_language(languages)?
+    } else {
+        select_language(languages)
+    }
     .ok_or_else(|| anyhow!("no language selected"))?;
 
     // Set up world
@@ -381,15 +709,133 @@ where
     world.clean();
     world.setup(true);
 
-    println(color::BRIGHT_MAGENTA, format!("lang: {lang}"));
+    notice(options, color::BRIGHT_MAGENTA, format!("lang: {lang}"));
+    Ok(())
+}
 
-    let mut db = sled::open(db_path).unwrap();
+pub fn start_cli_app<W, N, S>(
+    db_path: &str,
+    story: S,
+    mut world: W,
+    narrator: N,
+    options: &CliOptions,
+) -> Result<()>
+where
+    W: World,
+    N: Narrator,
+    S: ToString,
+{
+    setup_world(&mut world, options)?;
+    let mut db = sled::open(db_path)?;
+    // initial state is the first snapshot of the history
+    backend::store(&mut db, &story.to_string(), &world)?;
+    run_app(
+        Session::Local(db),
+        &story.to_string(),
+        world,
+        narrator,
+        None,
+        options,
+    )
+}
+
+/// Joins a world which is owned by another device connected to the server
+pub fn join_cli_app<W, N, S>(
+    remote: remote::RemoteWorld,
+    story: S,
+    mut world: W,
+    narrator: N,
+    options: &CliOptions,
+) -> Result<()>
+where
+    W: World,
+    N: Narrator,
+    S: ToString,
+{
+    let story = story.to_string();
+    setup_world(&mut world, options)?;
+    let mut connection = remote::Connection::connect(&remote, &story)?;
+    world.load(connection.get_world()?)?;
+    world.set_id(remote.world_id);
+    let character = world
+        .characters()
+        .get(&remote.character)
+        .ok_or_else(|| anyhow!("Failed to found character '{}'", remote.character))?;
+    connection.joined(character.dump())?;
+    notice(
+        options,
+        color::BRIGHT_MAGENTA,
+        format!(
+            "Joined world '{}' as '{}'",
+            remote.world_id, remote.character
+        ),
+    );
+    run_app(
+        Session::Remote(connection),
+        &story,
+        world,
+        narrator,
+        Some(&remote.character),
+        options,
+    )
+}
+
+fn run_app<W, N>(
+    mut session: Session,
+    story: &str,
+    mut world: W,
+    narrator: N,
+    character: Option<&str>,
+    options: &CliOptions,
+) -> Result<()>
+where
+    W: World,
+    N: Narrator,
+{
+    let mut recorder = options
+        .record
+        .as_ref()
+        .map(|path| transcript::Recorder::create(path, story))
+        .transpose()?;
+    if let Some(recorder) = recorder.as_mut() {
+        recorder.start(&world)?;
+    }
+    if options.plain {
+        return plain::run(
+            &mut session,
+            story,
+            &mut world,
+            &narrator,
+            character,
+            recorder.as_mut(),
+            options.parser,
+        );
+    }
 
     let mut state = View::Menu;
-    let mut selected_characters: Vec<PabitellItem> = vec![];
+    let mut selected_characters: Vec<PabitellItem> = character
+        .and_then(|character| world.characters().get(character))
+        .map(|e| {
+            vec![PabitellItem {
+                code: e.name().to_string(),
+                short: e.short(&world),
+                long: e.long(&world),
+            }]
+        })
+        .unwrap_or_default();
     let mut selected_items: Vec<PabitellItem> = vec![];
     let mut selected_scenes: Vec<PabitellItem> = vec![];
+    let mut selected_snapshot: Option<backend::Snapshot> = None;
     loop {
+        if let Session::Remote(connection) = &mut session {
+            sync_remote(
+                connection,
+                &mut world,
+                &narrator,
+                recorder.as_mut(),
+                &mut |text_color, text| println(text_color, text),
+            )?;
+        }
         match state {
             View::Menu => match main_menu(&world) {
                 Some(View::Items) => state = View::Items,
@@ -400,6 +846,8 @@ where
                 Some(View::Exit) => break,
                 _ => break,
             },
+            // character of a remote session is fixed
+            View::Characters if character.is_some() => state = View::Menu,
             View::Characters => {
                 if let Some(characters) = select_characters(&world) {
                     selected_characters = characters;
@@ -438,24 +886,21 @@ where
                         println(color::BRIGHT_GREEN, format!("\n{}\n\n", scene.long(&world)));
                     }
                 }
-                if let Some(events) = select_event(&mut world, &narrator) {
+                if let Some(events) = select_event(&world, &narrator, character) {
                     if !events.is_empty() {
                         let idx = events[0].idx;
-                        let mut events = narrator.available_events_sorted(&world);
-                        if events[idx].can_be_triggered(&world) {
-                            println(
-                                color::BRIGHT_CYAN,
-                                format!(
-                                    "{}. {}",
-                                    world.event_count() + 1,
-                                    events[idx].success_text(&world)
-                                ),
-                            );
-                        } else {
-                            println(color::BRIGHT_RED, events[idx].fail_text(&world));
+                        let mut events = available_events(&world, &narrator, character);
+                        match trigger_event(
+                            &mut session,
+                            story,
+                            &mut world,
+                            events[idx].as_mut(),
+                            recorder.as_mut(),
+                        )? {
+                            Triggered::Success(text) => println(color::BRIGHT_CYAN, text),
+                            Triggered::Failed(text) => println(color::BRIGHT_RED, text),
+                            Triggered::Sent => {}
                         }
-                        events[idx].trigger(&mut world);
-                        backend::store(&mut db, &story, &world).unwrap();
                         continue;
                     }
                 }
@@ -470,14 +915,30 @@ where
                 state = View::Menu;
             }
             View::Exit => break,
+            View::Reset | View::Load | View::Delete | View::History | View::Checkout
+                if matches!(session, Session::Remote(_)) =>
+            {
+                println(color::BRIGHT_RED, "Not available in a remote world");
+                state = View::Controls;
+            }
             View::Reset => {
                 world.reset();
+                if let Session::Local(db) = &mut session {
+                    backend::store(db, story, &world)?;
+                }
+                if let Some(recorder) = recorder.as_mut() {
+                    recorder.start(&world)?;
+                }
                 state = View::Menu;
             }
             View::Delete => {
                 println(color::BRIGHT_MAGENTA, "Deleting world");
-                if let Some(uuid) = select_stored_world(&db, &story).unwrap() {
-                    backend::delete(&mut db, &story, &uuid).unwrap();
+                let db = match &mut session {
+                    Session::Local(db) => db,
+                    Session::Remote(_) => unreachable!(),
+                };
+                if let Some(uuid) = select_stored_world(db, story).unwrap() {
+                    backend::delete(db, story, &uuid).unwrap();
                     println(
                         color::BRIGHT_MAGENTA,
                         format!("World '{}' was deleted", uuid),
@@ -487,10 +948,62 @@ where
                 }
                 state = View::Controls;
             }
+            View::History => {
+                let db = match &session {
+                    Session::Local(db) => db,
+                    Session::Remote(_) => unreachable!(),
+                };
+                selected_snapshot = select_snapshot(db, story, &world, &narrator)?;
+                state = if let Some(snapshot) = selected_snapshot.as_ref() {
+                    let changes = backend::diff(&world.dump(), &snapshot.world);
+                    println(
+                        color::BRIGHT_MAGENTA,
+                        format!("Changes when going back to event {}:", snapshot.event_count),
+                    );
+                    if changes.is_empty() {
+                        println(color::BRIGHT_MAGENTA, "no changes");
+                    } else {
+                        println(color::MAGENTA, format_changes(&changes));
+                    }
+                    history_menu().unwrap_or(View::Controls)
+                } else {
+                    View::Controls
+                };
+            }
+            View::Checkout => {
+                let db = match &mut session {
+                    Session::Local(db) => db,
+                    Session::Remote(_) => unreachable!(),
+                };
+                if let Some(snapshot) = selected_snapshot.take() {
+                    let uuid = *world.id();
+                    backend::checkout(db, story, &uuid, snapshot.event_count)?;
+                    backend::load(db, story, &uuid, &mut world)?;
+                    println(
+                        color::BRIGHT_MAGENTA,
+                        format!("World is back after event {}", snapshot.event_count),
+                    );
+                    if let Some(recorder) = recorder.as_mut() {
+                        recorder.start(&world)?;
+                    }
+                }
+                state = View::Menu;
+            }
+            View::Diagnose => {
+                if let Some(item) = select_diagnosis(&world, &narrator, character) {
+                    println(color::BRIGHT_MAGENTA, item.action);
+                    println(color::MAGENTA, item.diagnosis);
+                }
+                state = View::Controls;
+            }
             View::Load => {
                 println(color::BRIGHT_MAGENTA, "Loading world");
-                if let Some(uuid) = select_stored_world(&db, &story).unwrap() {
-                    if let Err(error) = backend::load(&db, &story, &uuid, &mut world) {
+                let db = match &session {
+                    Session::Local(db) => db,
+                    Session::Remote(_) => unreachable!(),
+                };
+                if let Some(uuid) = select_stored_world(db, story).unwrap() {
+                    if let Err(error) = backend::load(db, story, &uuid, &mut world) {
                         println(
                             color::BRIGHT_RED,
                             format!("Failed to load world '{}': {}", uuid, error),
@@ -500,6 +1013,9 @@ where
                             color::BRIGHT_MAGENTA,
                             format!("World '{}' was loaded", uuid),
                         );
+                        if let Some(recorder) = recorder.as_mut() {
+                            recorder.start(&world)?;
+                        }
                     }
                     state = View::Menu;
                 } else {
@@ -1,8 +1,42 @@
-use crate::World;
+use crate::{Event, World};
 use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use sled::Db;
+use std::time::{SystemTime, UNIX_EPOCH};
 use uuid::Uuid;
 
+/// State of the world after an event
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub event_count: usize,
+    /// Event which lead to this state (`None` for the initial state)
+    pub event: Option<Value>,
+    pub world: Value,
+    /// Seconds since the unix epoch
+    pub timestamp: u64,
+}
+
+/// Single difference between two JSON values
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Change {
+    /// Path to the changed value (e.g. `/items/cake`)
+    pub path: String,
+    pub old: Option<Value>,
+    pub new: Option<Value>,
+}
+
+fn history_tree(db: &Db, story: &str) -> Result<sled::Tree> {
+    Ok(db.open_tree(format!("{}-history", story))?)
+}
+
+/// Snapshots of a world are ordered by the event count
+fn snapshot_key(uuid: &Uuid, event_count: usize) -> Vec<u8> {
+    let mut key = uuid.as_bytes().to_vec();
+    key.extend_from_slice(&(event_count as u64).to_be_bytes());
+    key
+}
+
 pub fn list_stored(db: &Db, story: &str) -> Result<Vec<Uuid>> {
     let tree = db.open_tree(story)?;
     let results: Vec<Uuid> = tree
@@ -38,12 +72,159 @@ pub fn delete(db: &mut Db, story: &str, uuid: &Uuid) -> Result<()> {
     let tree = db.open_tree(story)?;
     tree.remove(uuid.as_bytes())?;
     tree.flush()?;
+    let history = history_tree(db, story)?;
+    for key in history.scan_prefix(uuid.as_bytes()).keys() {
+        history.remove(key?)?;
+    }
+    history.flush()?;
+    Ok(())
+}
+
+/// Removes snapshots which follow the event count
+/// (the world went a different way)
+fn discard_after(history: &sled::Tree, uuid: &Uuid, event_count: usize) -> Result<()> {
+    let start = snapshot_key(uuid, event_count + 1);
+    let end = snapshot_key(uuid, usize::MAX);
+    for key in history.range(start..=end).keys() {
+        history.remove(key?)?;
+    }
+    Ok(())
+}
+
+/// Stores the world and its snapshot
+/// snapshots after the current event count are discarded
+fn store_snapshot(db: &mut Db, story: &str, world: &dyn World, event: Option<Value>) -> Result<()> {
+    let data = world.dump();
+    let tree = db.open_tree(story)?;
+    tree.insert(world.id().as_bytes(), serde_json::to_vec(&data)?)?;
+    tree.flush()?;
+
+    let history = history_tree(db, story)?;
+    discard_after(&history, world.id(), world.event_count())?;
+    let snapshot = Snapshot {
+        event_count: world.event_count(),
+        event,
+        world: data,
+        timestamp: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|e| e.as_secs())
+            .unwrap_or_default(),
+    };
+    history.insert(
+        snapshot_key(world.id(), world.event_count()),
+        serde_json::to_vec(&snapshot)?,
+    )?;
+    history.flush()?;
     Ok(())
 }
 
 pub fn store(db: &mut Db, story: &str, world: &dyn World) -> Result<()> {
+    store_snapshot(db, story, world, None)
+}
+
+/// Stores the world after the event was triggered
+pub fn store_event(db: &mut Db, story: &str, world: &dyn World, event: &dyn Event) -> Result<()> {
+    store_snapshot(db, story, world, Some(event.dump()))
+}
+
+pub fn history(db: &Db, story: &str, uuid: &Uuid) -> Result<Vec<Snapshot>> {
+    history_tree(db, story)?
+        .scan_prefix(uuid.as_bytes())
+        .values()
+        .map(|e| Ok(serde_json::from_slice(&e?[..])?))
+        .collect()
+}
+
+pub fn snapshot(db: &Db, story: &str, uuid: &Uuid, event_count: usize) -> Result<Snapshot> {
+    let data = history_tree(db, story)?
+        .get(snapshot_key(uuid, event_count))?
+        .ok_or_else(|| anyhow!("No snapshot after event {}", event_count))?;
+    Ok(serde_json::from_slice(&data[..])?)
+}
+
+/// Restores the world to an earlier snapshot
+/// (later snapshots are discarded)
+pub fn checkout(db: &mut Db, story: &str, uuid: &Uuid, event_count: usize) -> Result<()> {
+    let snapshot = snapshot(db, story, uuid, event_count)?;
     let tree = db.open_tree(story)?;
-    tree.insert(world.id().as_bytes(), serde_json::to_vec(&world.dump())?)?;
+    tree.insert(uuid.as_bytes(), serde_json::to_vec(&snapshot.world)?)?;
     tree.flush()?;
+    let history = history_tree(db, story)?;
+    discard_after(&history, uuid, event_count)?;
+    history.flush()?;
     Ok(())
 }
+
+fn diff_into(old: Option<&Value>, new: Option<&Value>, path: String, changes: &mut Vec<Change>) {
+    match (old, new) {
+        (Some(Value::Object(old)), Some(Value::Object(new))) => {
+            let mut keys: Vec<&String> = old.keys().chain(new.keys()).collect();
+            keys.sort();
+            keys.dedup();
+            for key in keys {
+                diff_into(
+                    old.get(key),
+                    new.get(key),
+                    format!("{}/{}", path, key),
+                    changes,
+                );
+            }
+        }
+        (Some(Value::Array(old)), Some(Value::Array(new))) if old.len() == new.len() => {
+            for (idx, (old, new)) in old.iter().zip(new).enumerate() {
+                diff_into(Some(old), Some(new), format!("{}/{}", path, idx), changes);
+            }
+        }
+        (old, new) if old == new => {}
+        (old, new) => changes.push(Change {
+            path,
+            old: old.cloned(),
+            new: new.cloned(),
+        }),
+    }
+}
+
+/// Differences between two dumps of a world
+pub fn diff(old: &Value, new: &Value) -> Vec<Change> {
+    let mut changes = vec![];
+    diff_into(Some(old), Some(new), String::new(), &mut changes);
+    changes
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::{diff, Change};
+    use serde_json::json;
+
+    #[test]
+    fn world_diff() {
+        let world = json!({"items": {"cake": {"state": "kitchen"}}, "event_count": 1});
+        assert_eq!(diff(&world, &world.clone()), vec![]);
+        assert_eq!(
+            diff(
+                &world,
+                &json!({"items": {"cake": {"state": "doggie"}}, "event_count": 2})
+            ),
+            vec![
+                Change {
+                    path: "/event_count".to_string(),
+                    old: Some(json!(1)),
+                    new: Some(json!(2)),
+                },
+                Change {
+                    path: "/items/cake/state".to_string(),
+                    old: Some(json!("kitchen")),
+                    new: Some(json!("doggie")),
+                },
+            ]
+        );
+        assert_eq!(
+            diff(&world, &json!({"items": {"cake": {"state": "kitchen"}}})),
+            vec![Change {
+                path: "/event_count".to_string(),
+                old: Some(json!(1)),
+                new: None,
+            }]
+        );
+    }
+}
@@ -1,9 +1,232 @@
-use clap::{crate_authors, crate_description, crate_name, crate_version, App, Arg};
+use anyhow::{anyhow, Result};
+use clap::{crate_authors, crate_description, crate_name, crate_version, App, Arg, ArgMatches};
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    io::Read,
+    path::Path,
+};
+use uuid::Uuid;
 
-use super::start_cli_app;
-use crate::{Narrator, World};
+use super::{
+    backend, commands, join_cli_app, remote::RemoteWorld, start_cli_app, transcript, CliOptions,
+};
+use crate::{
+    explore::{self, ExploreOptions},
+    graph,
+    lint::{self, Messages},
+    Narrator, World,
+};
 
-pub fn run<W, N, S>(story: S, world: W, narrator: N)
+fn world_id(matches: &ArgMatches) -> Result<Uuid> {
+    let id = matches.value_of("id").unwrap();
+    Uuid::parse_str(id).map_err(|err| anyhow!("Invalid world id '{}': {}", id, err))
+}
+
+/// Reads JSON from the file or from stdin when the path is `-`
+fn read_json(path: &str) -> Result<serde_json::Value> {
+    let data = if path == "-" {
+        let mut data = String::new();
+        std::io::stdin().read_to_string(&mut data)?;
+        data
+    } else {
+        fs::read_to_string(path)?
+    };
+    Ok(serde_json::from_str(&data)?)
+}
+
+fn event_count(matches: &ArgMatches, name: &str) -> Result<usize> {
+    let value = matches.value_of(name).unwrap();
+    value
+        .parse()
+        .map_err(|err| anyhow!("Invalid event count '{}': {}", value, err))
+}
+
+fn explore_options(matches: &ArgMatches) -> Result<ExploreOptions> {
+    Ok(ExploreOptions {
+        max_depth: matches
+            .value_of("depth")
+            .unwrap()
+            .parse()
+            .map_err(|err| anyhow!("Invalid depth: {}", err))?,
+        max_states: matches
+            .value_of("max-states")
+            .unwrap()
+            .parse()
+            .map_err(|err| anyhow!("Invalid max states: {}", err))?,
+        ..Default::default()
+    })
+}
+
+fn explore_args() -> [Arg<'static>; 2] {
+    [
+        Arg::new("depth")
+            .help("Maximal number of events from the initial state")
+            .long("depth")
+            .takes_value(true)
+            .default_value("10"),
+        Arg::new("max-states")
+            .help("Exploration stops when this number of states is found")
+            .long("max-states")
+            .takes_value(true)
+            .default_value("10000"),
+    ]
+}
+
+/// Reads all fluent files of the language from `<dir>/<lang>/`
+fn read_messages(dir: &Path, lang: &str) -> Result<Messages> {
+    let lang_dir = dir.join(lang);
+    let mut messages = Messages::default();
+    let mut paths: Vec<_> = fs::read_dir(&lang_dir)
+        .map_err(|err| anyhow!("Failed to read '{}': {}", lang_dir.display(), err))?
+        .map(|e| e.map(|e| e.path()))
+        .collect::<Result<_, _>>()?;
+    paths.sort();
+    for path in paths {
+        if path.extension().map(|e| e == "ftl").unwrap_or(false) {
+            messages
+                .parse(&fs::read_to_string(&path)?)
+                .map_err(|err| anyhow!("{}: {}", path.display(), err))?;
+        }
+    }
+    Ok(messages)
+}
+
+fn id_arg() -> Arg<'static> {
+    Arg::new("id")
+        .help("Id of the stored world")
+        .value_name("WORLD_ID")
+        .required(true)
+}
+
+/// Path to the database of the stored worlds
+fn require_db_path(path: Option<&str>) -> Result<&str> {
+    path.ok_or_else(|| anyhow!("The database needs to be set using --db-path or PABITELL_DB_PATH"))
+}
+
+/// Opens the database only for subcommands which work with the stored worlds
+fn open_db(path: Option<&str>) -> Result<sled::Db> {
+    Ok(sled::open(require_db_path(path)?)?)
+}
+
+/// Runs the subcommand and prints its result as JSON
+fn run_command<W, N>(
+    db_path: Option<&str>,
+    story: &str,
+    world: &mut W,
+    narrator: &N,
+    matches: &ArgMatches,
+) -> Result<()>
+where
+    W: World,
+    N: Narrator,
+{
+    if let Some(lang) = matches.value_of("lang") {
+        if !world.set_lang(lang) {
+            return Err(anyhow!("Unsupported language '{}'", lang));
+        }
+    }
+    let db = || open_db(db_path);
+    let res = match matches.subcommand() {
+        Some(("new", _)) => commands::new(&mut db()?, story, world)?,
+        Some(("list", _)) => commands::list(&db()?, story)?,
+        Some(("show", sub)) => commands::show(&db()?, story, &world_id(sub)?, world)?,
+        Some(("events", sub)) => commands::events(
+            &db()?,
+            story,
+            &world_id(sub)?,
+            world,
+            narrator,
+            sub.is_present("all"),
+        )?,
+        Some(("trigger", sub)) => {
+            let event = serde_json::from_str(sub.value_of("event").unwrap())
+                .map_err(|err| anyhow!("Invalid event: {}", err))?;
+            commands::trigger(&mut db()?, story, &world_id(sub)?, world, narrator, event)?
+        }
+        Some(("export", sub)) => commands::export(&db()?, story, &world_id(sub)?, world)?,
+        Some(("import", sub)) => {
+            let data = read_json(sub.value_of("file").unwrap())?;
+            commands::import(&mut db()?, story, world, data, sub.is_present("new-id"))?
+        }
+        Some(("delete", sub)) => commands::delete(&mut db()?, story, &world_id(sub)?)?,
+        Some(("history", sub)) => {
+            commands::history(&db()?, story, &world_id(sub)?, world, narrator)?
+        }
+        Some(("diff", sub)) => commands::diff(
+            &db()?,
+            story,
+            &world_id(sub)?,
+            event_count(sub, "from")?,
+            event_count(sub, "to")?,
+        )?,
+        Some(("checkout", sub)) => commands::checkout(
+            &mut db()?,
+            story,
+            &world_id(sub)?,
+            event_count(sub, "event-count")?,
+        )?,
+        Some(("graph", sub)) => {
+            let format = sub.value_of("format").unwrap().parse()?;
+            world.clean();
+            world.setup(false);
+            print!("{}", graph::StoryGraph::new(world, narrator).render(format));
+            return Ok(());
+        }
+        Some(("explore", sub)) => {
+            let format: explore::Format = sub.value_of("format").unwrap().parse()?;
+            let options = explore_options(sub)?;
+            world.clean();
+            world.setup(true);
+            if sub.is_present("id") {
+                backend::load(&db()?, story, &world_id(sub)?, world)?;
+            }
+            let graph = explore::explore(world, narrator, &options)?;
+            print!("{}", graph.render(format)?);
+            return Ok(());
+        }
+        Some(("lint", sub)) => {
+            let options = explore_options(sub)?;
+            world.clean();
+            world.setup(true);
+            let (requests, truncated) = lint::requested_messages(world, narrator, &options)?;
+            let mut defined = HashMap::new();
+            if let Some(dir) = sub.value_of("resources") {
+                for lang in requests.keys() {
+                    defined.insert(lang.clone(), read_messages(Path::new(dir), lang)?);
+                }
+            }
+            let report = lint::Report {
+                requested: requests
+                    .values()
+                    .flat_map(|e| e.keys())
+                    .collect::<HashSet<_>>()
+                    .len(),
+                truncated,
+                issues: lint::lint(&requests, &defined, sub.is_present("unused-arguments")),
+            };
+            println!("{}", serde_json::to_string_pretty(&report)?);
+            if !report.issues.is_empty() {
+                return Err(anyhow!("Found {} localization issues", report.issues.len()));
+            }
+            return Ok(());
+        }
+        Some(("replay", sub)) => {
+            let entries = transcript::read(Path::new(sub.value_of("file").unwrap()))?;
+            let report = transcript::replay(&entries, story, world, narrator)?;
+            println!("{}", serde_json::to_string_pretty(&report)?);
+            if report.divergence.is_some() {
+                return Err(anyhow!("Transcript diverged"));
+            }
+            return Ok(());
+        }
+        _ => unreachable!(),
+    };
+    println!("{}", serde_json::to_string_pretty(&res)?);
+    Ok(())
+}
+
+pub fn run<W, N, S>(story: S, mut world: W, narrator: N)
 where
     W: World,
     N: Narrator,
@@ -13,15 +236,255 @@ where
         .author(crate_authors!())
         .version(crate_version!())
         .about(crate_description!())
-        .args(&[Arg::new("db-path")
-            .short('P')
-            .long("db-path")
-            .value_name("PATH")
-            .takes_value(true)
-            .required(true)
-            .env("PABITELL_DB_PATH")]);
+        .args(&[
+            Arg::new("db-path")
+                .short('P')
+                .long("db-path")
+                .value_name("PATH")
+                .help("Database of the stored worlds (not needed by graph, explore, lint and replay)")
+                .takes_value(true)
+                .env("PABITELL_DB_PATH"),
+            Arg::new("lang")
+                .help("Language of the texts")
+                .short('l')
+                .long("lang")
+                .value_name("LANG")
+                .takes_value(true)
+                .global(true)
+                .env("PABITELL_LANG"),
+            Arg::new("record")
+                .help("Records triggered events to a transcript file")
+                .long("record")
+                .value_name("FILE")
+                .takes_value(true)
+                .global(true),
+            Arg::new("plain")
+                .help("Use numbered menus and typed commands instead of the fullscreen finder")
+                .long("plain")
+                .global(true)
+                .env("PABITELL_PLAIN"),
+            Arg::new("parser")
+                .help("Plain frontend which also understands typed actions (e.g. 'give cake to kitie')")
+                .long("parser")
+                .global(true)
+                .env("PABITELL_PARSER"),
+        ])
+        .subcommand(App::new("new").about("Creates a new world"))
+        .subcommand(App::new("list").about("Lists stored worlds"))
+        .subcommand(
+            App::new("show")
+                .about("Shows the state of a world")
+                .arg(id_arg()),
+        )
+        .subcommand(
+            App::new("events")
+                .about("Lists events which can be triggered")
+                .arg(id_arg())
+                .arg(
+                    Arg::new("all")
+                        .help("Include events which are not available")
+                        .long("all"),
+                ),
+        )
+        .subcommand(
+            App::new("trigger")
+                .about("Triggers an event (as listed by `events`)")
+                .arg(id_arg())
+                .arg(
+                    Arg::new("event")
+                        .help("Event in JSON")
+                        .value_name("EVENT_JSON")
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            App::new("export")
+                .about("Prints a world in the import format")
+                .arg(id_arg()),
+        )
+        .subcommand(
+            App::new("import")
+                .about("Stores an exported world")
+                .arg(
+                    Arg::new("file")
+                        .help("File with the exported world ('-' for stdin)")
+                        .value_name("FILE")
+                        .default_value("-"),
+                )
+                .arg(
+                    Arg::new("new-id")
+                        .help("Assign a new id to the imported world")
+                        .long("new-id"),
+                ),
+        )
+        .subcommand(
+            App::new("delete")
+                .about("Deletes a stored world")
+                .arg(id_arg()),
+        )
+        .subcommand(
+            App::new("history")
+                .about("Lists stored snapshots of a world")
+                .arg(id_arg()),
+        )
+        .subcommand(
+            App::new("diff")
+                .about("Shows changes of a world between two snapshots")
+                .arg(id_arg())
+                .arg(
+                    Arg::new("from")
+                        .help("Event count of the older snapshot")
+                        .value_name("FROM")
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("to")
+                        .help("Event count of the newer snapshot")
+                        .value_name("TO")
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            App::new("checkout")
+                .about("Restores a world to an earlier snapshot (later snapshots are discarded)")
+                .arg(id_arg())
+                .arg(
+                    Arg::new("event-count")
+                        .help("Event count of the snapshot")
+                        .value_name("EVENT_COUNT")
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            App::new("join")
+                .about("Joins a world on pabitell-webserver")
+                .arg(
+                    Arg::new("url")
+                        .help("Url of the server (ws://localhost:8080 or wss://example.com for servers with TLS)")
+                        .value_name("URL")
+                        .required(true),
+                )
+                .arg(id_arg().help("Id of the shared world"))
+                .arg(
+                    Arg::new("character")
+                        .help("Character which will be played")
+                        .short('c')
+                        .long("character")
+                        .takes_value(true)
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("namespace")
+                        .help("Namespace of the world on the server")
+                        .long("namespace")
+                        .takes_value(true)
+                        .default_value("default"),
+                )
+                .arg(
+                    Arg::new("secret")
+                        .help("Join secret obtained from the owner of the world")
+                        .long("secret")
+                        .takes_value(true)
+                        .env("PABITELL_SECRET"),
+                ),
+        )
+        .subcommand(
+            App::new("graph")
+                .about("Prints a diagram of scenes, characters, items and events")
+                .arg(
+                    Arg::new("format")
+                        .help("Format of the diagram")
+                        .short('f')
+                        .long("format")
+                        .takes_value(true)
+                        .possible_values(["dot", "mermaid"])
+                        .default_value("dot"),
+                ),
+        )
+        .subcommand(
+            App::new("explore")
+                .about("Prints a graph of reachable world states and events between them")
+                .arg(
+                    id_arg()
+                        .required(false)
+                        .help("Explore from a stored world (a new world is used by default)"),
+                )
+                .arg(
+                    Arg::new("format")
+                        .help("Format of the graph")
+                        .short('f')
+                        .long("format")
+                        .takes_value(true)
+                        .possible_values(["dot", "json"])
+                        .default_value("dot"),
+                )
+                .args(explore_args()),
+        )
+        .subcommand(
+            App::new("lint")
+                .about("Reports missing and unused translations and missing arguments")
+                .arg(
+                    Arg::new("resources")
+                        .help("Directory with '<lang>/*.ftl' files of the story (only missing messages are reported without it)")
+                        .long("resources")
+                        .value_name("DIR")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::new("unused-arguments")
+                        .help("Also report arguments which are passed to messages but not used in them")
+                        .long("unused-arguments"),
+                )
+                .args(explore_args()),
+        )
+        .subcommand(
+            App::new("replay")
+                .about("Replays a transcript and reports the first divergence")
+                .arg(
+                    Arg::new("file")
+                        .help("Transcript recorded using --record")
+                        .value_name("FILE")
+                        .required(true),
+                ),
+        );
 
     let matches = app.clone().get_matches();
-    let db_path = matches.value_of("db-path").unwrap();
-    start_cli_app(db_path, story, world, narrator).unwrap();
+    let db_path = matches.value_of("db-path");
+    let options = CliOptions {
+        record: matches.value_of("record").map(Into::into),
+        plain: matches.is_present("plain") || matches.is_present("parser"),
+        parser: matches.is_present("parser"),
+    };
+    match matches.subcommand() {
+        None => {
+            if let Err(err) = require_db_path(db_path)
+                .and_then(|path| start_cli_app(path, story, world, narrator, &options))
+            {
+                eprintln!("{}", err);
+                std::process::exit(1);
+            }
+            return;
+        }
+        Some(("join", sub)) => {
+            let remote = world_id(sub).map(|world_id| RemoteWorld {
+                url: sub.value_of("url").unwrap().to_string(),
+                namespace: sub.value_of("namespace").unwrap().to_string(),
+                world_id,
+                secret: sub.value_of("secret").map(String::from),
+                character: sub.value_of("character").unwrap().to_string(),
+            });
+            if let Err(err) = remote.and_then(|e| join_cli_app(e, story, world, narrator, &options))
+            {
+                eprintln!("{}", err);
+                std::process::exit(1);
+            }
+            return;
+        }
+        _ => {}
+    }
+    let story = story.to_string();
+    if let Err(err) = run_command(db_path, &story, &mut world, &narrator, &matches) {
+        eprintln!("{}", err);
+        std::process::exit(1);
+    }
 }
@@ -0,0 +1,229 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sled::Db;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+use super::backend;
+use crate::{Dumpable, Narrator, World};
+
+/// World as it is exported and imported
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ExportedWorld {
+    pub id: Uuid,
+    pub story: String,
+    pub version: usize,
+    pub world: Value,
+}
+
+/// Sets up the world and loads the stored data into it
+fn load(db: &Db, story: &str, id: &Uuid, world: &mut dyn World) -> Result<()> {
+    world.clean();
+    world.setup(false);
+    backend::load(db, story, id, world)
+}
+
+/// Creates a new world and stores it
+pub fn new(db: &mut Db, story: &str, world: &mut dyn World) -> Result<Value> {
+    world.clean();
+    world.setup(true);
+    backend::store(db, story, world)?;
+    Ok(json!({ "id": world.id() }))
+}
+
+pub fn list(db: &Db, story: &str) -> Result<Value> {
+    Ok(json!(backend::list_stored(db, story)?))
+}
+
+/// Summary of the stored world
+pub fn show(db: &Db, story: &str, id: &Uuid, world: &mut dyn World) -> Result<Value> {
+    load(db, story, id, world)?;
+    let characters: HashMap<&str, &Option<String>> = world
+        .characters()
+        .values()
+        .map(|e| (e.name(), e.scene()))
+        .collect();
+    let items: HashMap<&str, Value> = world
+        .items()
+        .values()
+        .map(|e| (e.name(), e.state().dump()))
+        .collect();
+    let mut scenes: Vec<&str> = world.scenes().values().map(|e| e.name()).collect();
+    scenes.sort_unstable();
+    Ok(json!({
+        "id": world.id(),
+        "story": story,
+        "lang": world.lang(),
+        "description": world.description().short(world),
+        "event_count": world.event_count(),
+        "finished": world.finished(),
+        "characters": characters,
+        "items": items,
+        "scenes": scenes,
+    }))
+}
+
+/// Events of the stored world (only the available ones unless `all` is set)
+pub fn events(
+    db: &Db,
+    story: &str,
+    id: &Uuid,
+    world: &mut dyn World,
+    narrator: &dyn Narrator,
+    all: bool,
+) -> Result<Value> {
+    load(db, story, id, world)?;
+    let events = if all {
+        let mut events = narrator.all_events(world);
+        events.sort_by_key(|e| e.sort_key(world));
+        events
+    } else {
+        narrator.available_events_sorted(world)
+    };
+    Ok(Value::Array(
+        events
+            .iter()
+            .map(|e| {
+                let available = e.can_be_triggered(world);
+                json!({
+                    "event": e.dump(),
+                    "initiator": e.initiator(),
+                    "action": e.action_text(world),
+                    "available": available,
+                    "diagnosis": if available {
+                        None
+                    } else {
+                        Some(e.get_condition().diagnose(world))
+                    },
+                })
+            })
+            .collect(),
+    ))
+}
+
+/// Triggers the event and stores the world when the event succeeds
+pub fn trigger(
+    db: &mut Db,
+    story: &str,
+    id: &Uuid,
+    world: &mut dyn World,
+    narrator: &dyn Narrator,
+    event: Value,
+) -> Result<Value> {
+    load(db, story, id, world)?;
+    let mut event = narrator
+        .parse_event(world, event.clone())
+        .ok_or_else(|| anyhow!("Unknown event {}", event))?;
+    // diagnosed before the world changes
+    let diagnosis = event.get_condition().diagnose(world);
+    let success = event.perform(world);
+    let text = if success {
+        backend::store_event(db, story, world, event.as_ref())?;
+        event.success_text(world)
+    } else {
+        event.fail_text(world)
+    };
+    Ok(json!({
+        "id": world.id(),
+        "success": success,
+        "text": text,
+        "event_count": world.event_count(),
+        "diagnosis": if success { None } else { Some(diagnosis) },
+    }))
+}
+
+pub fn export(db: &Db, story: &str, id: &Uuid, world: &mut dyn World) -> Result<Value> {
+    load(db, story, id, world)?;
+    Ok(serde_json::to_value(ExportedWorld {
+        id: *id,
+        story: story.to_string(),
+        version: world.version(),
+        world: world.dump(),
+    })?)
+}
+
+/// Stores an exported world (a new id is assigned when `new_id` is set)
+pub fn import(
+    db: &mut Db,
+    story: &str,
+    world: &mut dyn World,
+    data: Value,
+    new_id: bool,
+) -> Result<Value> {
+    let exported: ExportedWorld = serde_json::from_value(data)?;
+    if exported.story != story {
+        return Err(anyhow!(
+            "World belongs to story '{}' not '{}'",
+            exported.story,
+            story
+        ));
+    }
+    if exported.version != world.version() {
+        return Err(anyhow!(
+            "World version {} doesn't match {}",
+            exported.version,
+            world.version()
+        ));
+    }
+    world.clean();
+    world.setup(false);
+    world.load(exported.world)?;
+    if new_id {
+        world.randomize_id();
+    } else {
+        world.set_id(exported.id);
+    }
+    backend::store(db, story, world)?;
+    Ok(json!({ "id": world.id() }))
+}
+
+pub fn delete(db: &mut Db, story: &str, id: &Uuid) -> Result<Value> {
+    if !backend::list_stored(db, story)?.contains(id) {
+        return Err(anyhow!("World '{}' not found", id));
+    }
+    backend::delete(db, story, id)?;
+    Ok(json!({ "id": id }))
+}
+
+/// Stored snapshots of a world
+pub fn history(
+    db: &Db,
+    story: &str,
+    id: &Uuid,
+    world: &mut dyn World,
+    narrator: &dyn Narrator,
+) -> Result<Value> {
+    load(db, story, id, world)?;
+    Ok(Value::Array(
+        backend::history(db, story, id)?
+            .into_iter()
+            .map(|snapshot| {
+                let action = snapshot
+                    .event
+                    .clone()
+                    .and_then(|e| narrator.parse_event(world, e))
+                    .map(|e| e.action_text(world));
+                json!({
+                    "event_count": snapshot.event_count,
+                    "event": snapshot.event,
+                    "action": action,
+                    "timestamp": snapshot.timestamp,
+                })
+            })
+            .collect(),
+    ))
+}
+
+/// Changes of the world between two snapshots
+pub fn diff(db: &Db, story: &str, id: &Uuid, from: usize, to: usize) -> Result<Value> {
+    let from = backend::snapshot(db, story, id, from)?;
+    let to = backend::snapshot(db, story, id, to)?;
+    Ok(json!(backend::diff(&from.world, &to.world)))
+}
+
+/// Restores the world to the state after the given event
+pub fn checkout(db: &mut Db, story: &str, id: &Uuid, event_count: usize) -> Result<Value> {
+    backend::checkout(db, story, id, event_count)?;
+    Ok(json!({ "id": id, "event_count": event_count }))
+}
@@ -0,0 +1,106 @@
+use serde_json::Value;
+use strsim::normalized_damerau_levenshtein;
+
+use crate::{Event, World};
+
+/// Candidates with a lower score are not considered at all
+const MIN_SCORE: f64 = 0.6;
+/// Candidates which are this close to the best one are ambiguous
+const AMBIGUITY: f64 = 0.05;
+
+/// Lowercased words of the text without punctuation
+fn words(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|e| !e.is_empty())
+        .map(|e| e.to_lowercase())
+        .collect()
+}
+
+fn data_strings(value: &Value, res: &mut Vec<String>) {
+    match value {
+        Value::String(text) => res.push(text.clone()),
+        Value::Array(values) => values.iter().for_each(|e| data_strings(e, res)),
+        Value::Object(map) => map.values().for_each(|e| data_strings(e, res)),
+        _ => {}
+    }
+}
+
+/// Words which can be used to refer to the event
+///
+/// i.e. words of its action text, names from its data
+/// and localized names of the referred items, characters and scenes
+pub fn event_words(event: &dyn Event, world: &dyn World) -> Vec<String> {
+    let mut texts = vec![event.action_text(world)];
+    let mut names = vec![];
+    data_strings(&event.dump(), &mut names);
+    for name in names {
+        if let Some(item) = world.items().get(&name) {
+            texts.push(item.short(world));
+        } else if let Some(character) = world.characters().get(&name) {
+            texts.push(character.short(world));
+        } else if let Some(scene) = world.scenes().get(&name) {
+            texts.push(scene.short(world));
+        }
+        texts.push(name);
+    }
+    let mut res: Vec<String> = texts.iter().flat_map(|e| words(e)).collect();
+    res.sort_unstable();
+    res.dedup();
+    res
+}
+
+/// How well the typed text matches the words of an event (0.0 - 1.0)
+pub fn score(input: &str, event_words: &[String]) -> f64 {
+    let input = words(input);
+    if input.is_empty() || event_words.is_empty() {
+        return 0.0;
+    }
+    input
+        .iter()
+        .map(|word| {
+            event_words
+                .iter()
+                .map(|e| normalized_damerau_levenshtein(word, e))
+                .fold(0.0, f64::max)
+        })
+        .sum::<f64>()
+        / input.len() as f64
+}
+
+/// Indexes of the events which match the typed text the best
+///
+/// more than one index means that the text is ambiguous
+pub fn best_matches(input: &str, events: &[Vec<String>]) -> Vec<usize> {
+    let scores: Vec<f64> = events.iter().map(|e| score(input, e)).collect();
+    let best = scores.iter().cloned().fold(0.0, f64::max);
+    if best < MIN_SCORE {
+        return vec![];
+    }
+    scores
+        .iter()
+        .enumerate()
+        .filter(|(_, score)| **score >= best - AMBIGUITY)
+        .map(|(idx, _)| idx)
+        .collect()
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::{best_matches, words};
+
+    #[test]
+    fn match_events() {
+        let events = vec![
+            words("Give cake to Kitie"),
+            words("Give cake to Doggie"),
+            words("Go to kitchen"),
+        ];
+        assert_eq!(best_matches("give cake to kitie", &events), vec![0]);
+        // typos
+        assert_eq!(best_matches("giv cake to kity", &events), vec![0]);
+        assert_eq!(best_matches("go to the kitchen", &events), vec![2]);
+        // ambiguous
+        assert_eq!(best_matches("give cake", &events), vec![0, 1]);
+        assert_eq!(best_matches("dance", &events), Vec::<usize>::new());
+    }
+}
@@ -0,0 +1,429 @@
+use anyhow::Result;
+use std::io::{self, BufRead, Write};
+
+use super::{
+    available_events, backend, parser, sync_remote, transcript, trigger_event, unavailable_events,
+    Session, Triggered,
+};
+use crate::{events, Event, ItemState, Narrator, World};
+
+const HELP: &str = "\
+Commands:
+  events            list events which can be triggered (also an empty line)
+  <number>          trigger an event from the last list
+  look              describe the scene of the character
+  inventory         list items of the character
+  go <scene>        move the character to another scene
+  take <item>       pick up an item
+  character [name]  list characters or play as the character
+  why               explain why the other events can't be triggered
+  reset             start the story again
+  help              show this help
+  quit              leave the app";
+
+const PARSER_HELP: &str = "\
+Anything else is matched against the actions which can be done (e.g. 'give cake to kitie')";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Command {
+    Help,
+    Events,
+    Trigger(usize),
+    Look,
+    Inventory,
+    Go(String),
+    Take(String),
+    Character(Option<String>),
+    Why,
+    Reset,
+    Quit,
+    Unknown(String),
+}
+
+impl Command {
+    fn parse(line: &str) -> Self {
+        let line = line.trim();
+        let (command, arg) = line
+            .split_once(char::is_whitespace)
+            .map(|(command, arg)| (command, Some(arg.trim().to_string())))
+            .unwrap_or((line, None));
+        match (command.to_lowercase().as_str(), arg) {
+            ("" | "events" | "e", None) => Self::Events,
+            (number, None) if number.parse::<usize>().is_ok() => {
+                Self::Trigger(number.parse().unwrap())
+            }
+            ("help" | "h" | "?", None) => Self::Help,
+            ("look" | "l", None) => Self::Look,
+            ("inventory" | "i", None) => Self::Inventory,
+            ("go", Some(scene)) => Self::Go(scene),
+            ("take", Some(item)) => Self::Take(item),
+            ("character" | "c", character) => Self::Character(character),
+            ("why", None) => Self::Why,
+            ("reset", None) => Self::Reset,
+            ("quit" | "exit" | "q", None) => Self::Quit,
+            _ => Self::Unknown(line.to_string()),
+        }
+    }
+}
+
+/// Reads a line from stdin (`None` when the input is closed)
+fn read_line(prompt: &str) -> Result<Option<String>> {
+    print!("{}", prompt);
+    io::stdout().flush()?;
+    let mut line = String::new();
+    if io::stdin().lock().read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+    Ok(Some(line.trim().to_string()))
+}
+
+/// Numbered menu for picking the language
+pub(super) fn select_language(available_languages: Vec<String>) -> Result<Option<String>> {
+    if available_languages.len() == 1 {
+        return Ok(available_languages.into_iter().next());
+    }
+    for (idx, lang) in available_languages.iter().enumerate() {
+        println!("{}. {}", idx + 1, lang);
+    }
+    loop {
+        let line = if let Some(line) = read_line("language> ")? {
+            line
+        } else {
+            return Ok(None);
+        };
+        let selected = line
+            .parse::<usize>()
+            .ok()
+            .and_then(|idx| available_languages.get(idx.wrapping_sub(1)))
+            .or_else(|| available_languages.iter().find(|e| **e == line));
+        if let Some(lang) = selected {
+            return Ok(Some(lang.to_string()));
+        }
+        println!("Type a number or a language from the list");
+    }
+}
+
+/// Name or the short description matches the text typed by the user
+fn is_named(name: &str, short: String, text: &str) -> bool {
+    name.eq_ignore_ascii_case(text) || short.to_lowercase() == text.to_lowercase()
+}
+
+/// Available event of the character which is selected by `select`
+fn find_event<F>(
+    world: &dyn World,
+    narrator: &dyn Narrator,
+    character: Option<&str>,
+    select: F,
+) -> Option<Box<dyn Event>>
+where
+    F: Fn(&dyn Event) -> bool,
+{
+    available_events(world, narrator, character)
+        .into_iter()
+        .find(|e| select(e.as_ref()))
+}
+
+fn look(world: &dyn World, character: Option<&str>) {
+    let scene = character
+        .and_then(|e| world.characters().get(e))
+        .and_then(|e| e.scene().as_ref())
+        .and_then(|e| world.scenes().get(e));
+    let scene = if let Some(scene) = scene {
+        scene
+    } else {
+        println!("{}", world.description().long(world));
+        return;
+    };
+    println!("{}", scene.long(world));
+    let items: Vec<String> = world
+        .items()
+        .values()
+        .filter(|e| matches!(e.state(), ItemState::InScene(s) if s == scene.name()))
+        .map(|e| e.short(world))
+        .collect();
+    if !items.is_empty() {
+        println!("Items: {}", items.join(", "));
+    }
+    let characters: Vec<String> = world
+        .characters()
+        .values()
+        .filter(|e| Some(e.name()) != character && e.scene().as_deref() == Some(scene.name()))
+        .map(|e| e.short(world))
+        .collect();
+    if !characters.is_empty() {
+        println!("Characters: {}", characters.join(", "));
+    }
+}
+
+fn inventory(world: &dyn World, character: &str) {
+    let items: Vec<String> = world
+        .items()
+        .values()
+        .filter(|e| matches!(e.state(), ItemState::Owned(c) if c == character))
+        .map(|e| e.short(world))
+        .collect();
+    if items.is_empty() {
+        println!("Nothing");
+    } else {
+        println!("{}", items.join(", "));
+    }
+}
+
+fn list_events(events: &[Box<dyn Event>], world: &dyn World) {
+    if events.is_empty() {
+        println!("Nothing can be done now");
+    }
+    for (idx, event) in events.iter().enumerate() {
+        println!("{}. {}", idx + 1, event.action_text(world));
+    }
+}
+
+/// Finds the available event which the user described
+///
+/// asks the user to choose when more events match
+fn parse_event(
+    world: &dyn World,
+    narrator: &dyn Narrator,
+    character: Option<&str>,
+    line: &str,
+) -> Result<Option<Box<dyn Event>>> {
+    let mut events = available_events(world, narrator, character);
+    let words: Vec<Vec<String>> = events
+        .iter()
+        .map(|e| parser::event_words(e.as_ref(), world))
+        .collect();
+    let matched = parser::best_matches(line, &words);
+    let idx = match matched.len() {
+        0 => {
+            println!("I don't understand '{}' (type 'help')", line);
+            return Ok(None);
+        }
+        1 => matched[0],
+        _ => {
+            println!("Did you mean:");
+            for (number, idx) in matched.iter().enumerate() {
+                println!("{}. {}", number + 1, events[*idx].action_text(world));
+            }
+            let choice = read_line("which> ")?
+                .and_then(|e| e.parse::<usize>().ok())
+                .and_then(|e| matched.get(e.wrapping_sub(1)));
+            if let Some(idx) = choice {
+                *idx
+            } else {
+                println!("Nothing was done");
+                return Ok(None);
+            }
+        }
+    };
+    Ok(Some(events.swap_remove(idx)))
+}
+
+/// Line based frontend which reads commands from stdin
+pub(super) fn run(
+    session: &mut Session,
+    story: &str,
+    world: &mut dyn World,
+    narrator: &dyn Narrator,
+    character: Option<&str>,
+    recorder: Option<&mut transcript::Recorder>,
+    parser: bool,
+) -> Result<()> {
+    let mut recorder = recorder;
+    // character of a remote session is fixed
+    let fixed = character.is_some();
+    let mut character = character.map(String::from);
+    println!("{}", world.description().short(world));
+    println!("Type 'help' to list the commands");
+    loop {
+        if let Session::Remote(connection) = session {
+            sync_remote(
+                connection,
+                world,
+                narrator,
+                recorder.as_deref_mut(),
+                &mut |_, text| println!("{}", text),
+            )?;
+        }
+        let prompt = format!("{}> ", character.as_deref().unwrap_or_default());
+        let line = if let Some(line) = read_line(&prompt)? {
+            line
+        } else {
+            break;
+        };
+
+        let mut event = match Command::parse(&line) {
+            Command::Help => {
+                println!("{}", HELP);
+                if parser {
+                    println!("{}", PARSER_HELP);
+                }
+                None
+            }
+            Command::Events => {
+                list_events(
+                    &available_events(world, narrator, character.as_deref()),
+                    world,
+                );
+                None
+            }
+            Command::Trigger(number) => {
+                let event = available_events(world, narrator, character.as_deref())
+                    .into_iter()
+                    .nth(number.wrapping_sub(1));
+                if event.is_none() {
+                    println!("No event with number {}", number);
+                }
+                event
+            }
+            Command::Look => {
+                look(world, character.as_deref());
+                None
+            }
+            Command::Inventory => {
+                if let Some(character) = character.as_deref() {
+                    inventory(world, character);
+                } else {
+                    println!("Select a character first");
+                }
+                None
+            }
+            Command::Go(text) => {
+                let target = world
+                    .scenes()
+                    .values()
+                    .find(|e| is_named(e.name(), e.short(world), &text))
+                    .map(|e| e.name().to_string());
+                let event = target.and_then(|scene| {
+                    find_event(world, narrator, character.as_deref(), |e| {
+                        e.as_any()
+                            .downcast_ref::<events::Move>()
+                            .map(|e| e.scene() == scene)
+                            .unwrap_or(false)
+                    })
+                });
+                if event.is_none() {
+                    println!("You can't go to '{}' now", text);
+                }
+                event
+            }
+            Command::Take(text) => {
+                let target = world
+                    .items()
+                    .values()
+                    .find(|e| is_named(e.name(), e.short(world), &text))
+                    .map(|e| e.name().to_string());
+                let event = target.and_then(|item| {
+                    find_event(world, narrator, character.as_deref(), |e| {
+                        e.as_any()
+                            .downcast_ref::<events::Pick>()
+                            .map(|e| e.item() == item)
+                            .unwrap_or(false)
+                    })
+                });
+                if event.is_none() {
+                    println!("You can't take '{}' now", text);
+                }
+                event
+            }
+            Command::Character(_) if fixed => {
+                println!("Character can't be changed in a remote world");
+                None
+            }
+            Command::Character(None) => {
+                let mut names: Vec<&str> = world.characters().keys().map(|e| e.as_str()).collect();
+                names.sort_unstable();
+                for name in names {
+                    let character = world.characters().get(name).unwrap();
+                    println!("{} - {}", name, character.short(world));
+                }
+                None
+            }
+            Command::Character(Some(text)) => {
+                if let Some(selected) = world
+                    .characters()
+                    .values()
+                    .find(|e| is_named(e.name(), e.short(world), &text))
+                {
+                    character = Some(selected.name().to_string());
+                } else {
+                    println!("Unknown character '{}'", text);
+                }
+                None
+            }
+            Command::Why => {
+                let events = unavailable_events(world, narrator, character.as_deref());
+                if events.is_empty() {
+                    println!("All events can be triggered");
+                }
+                for event in events {
+                    println!("{}", event.action_text(world));
+                    for failure in event.get_condition().diagnose(world).failures() {
+                        println!("  {}", failure);
+                    }
+                }
+                None
+            }
+            Command::Reset => {
+                if let Session::Local(db) = session {
+                    world.reset();
+                    backend::store(db, story, world)?;
+                    if let Some(recorder) = recorder.as_mut() {
+                        recorder.start(world)?;
+                    }
+                    println!("{}", world.description().short(world));
+                } else {
+                    println!("Not available in a remote world");
+                }
+                None
+            }
+            Command::Quit => break,
+            Command::Unknown(line) if parser => {
+                parse_event(world, narrator, character.as_deref(), &line)?
+            }
+            Command::Unknown(line) => {
+                println!("Unknown command '{}' (type 'help')", line);
+                None
+            }
+        };
+
+        if let Some(event) = event.as_mut() {
+            match trigger_event(
+                session,
+                story,
+                world,
+                event.as_mut(),
+                recorder.as_deref_mut(),
+            )? {
+                Triggered::Success(text) | Triggered::Failed(text) => println!("{}", text),
+                Triggered::Sent => {}
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::Command;
+
+    #[test]
+    fn parse_commands() {
+        assert_eq!(Command::parse(""), Command::Events);
+        assert_eq!(Command::parse(" 3 "), Command::Trigger(3));
+        assert_eq!(Command::parse("LOOK"), Command::Look);
+        assert_eq!(
+            Command::parse("go  living room"),
+            Command::Go("living room".to_string())
+        );
+        assert_eq!(
+            Command::parse("take cake"),
+            Command::Take("cake".to_string())
+        );
+        assert_eq!(Command::parse("character"), Command::Character(None));
+        assert_eq!(
+            Command::parse("c doggie"),
+            Command::Character(Some("doggie".to_string()))
+        );
+        assert_eq!(Command::parse("go"), Command::Unknown("go".to_string()));
+    }
+}
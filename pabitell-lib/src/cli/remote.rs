@@ -0,0 +1,232 @@
+use anyhow::{anyhow, Result};
+use serde_json::Value;
+use std::{
+    net::TcpStream,
+    sync::mpsc::{self, Receiver, RecvTimeoutError, Sender, TryRecvError},
+    thread,
+    time::{Duration, Instant},
+};
+use tungstenite::{
+    client::IntoClientRequest,
+    http::{header::SEC_WEBSOCKET_PROTOCOL, HeaderValue},
+    stream::MaybeTlsStream,
+    Message, WebSocket,
+};
+use uuid::Uuid;
+
+use crate::protocol;
+
+/// How long to wait for a response to a request
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+/// How often the connection thread checks for outgoing messages
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// World which is shared via pabitell-webserver
+#[derive(Debug, Clone)]
+pub struct RemoteWorld {
+    /// Base url of the server (e.g. `ws://localhost:8080` or `wss://example.com`)
+    pub url: String,
+    pub namespace: String,
+    pub world_id: Uuid,
+    /// Join secret obtained from the owner of the world
+    pub secret: Option<String>,
+    /// Character which is played
+    pub character: String,
+}
+
+impl RemoteWorld {
+    fn ws_url(&self, story: &str) -> String {
+        format!(
+            "{}/ws/{}/{}/{}/",
+            self.url.trim_end_matches('/'),
+            self.namespace,
+            story,
+            self.world_id,
+        )
+    }
+}
+
+/// Connection to a world on the server
+///
+/// the socket is handled in a separate thread
+/// so heartbeats are answered while the user is choosing
+pub struct Connection {
+    world_id: Uuid,
+    outgoing: Sender<String>,
+    incoming: Receiver<Result<protocol::Message, String>>,
+    /// Notifications which arrived while waiting for a response
+    notifications: Vec<protocol::NotificationMessage>,
+}
+
+fn handle_socket(
+    mut socket: WebSocket<MaybeTlsStream<TcpStream>>,
+    outgoing: Receiver<String>,
+    incoming: Sender<Result<protocol::Message, String>>,
+) {
+    loop {
+        loop {
+            match outgoing.try_recv() {
+                Ok(data) => {
+                    if let Err(err) = socket.write_message(Message::Text(data)) {
+                        let _ = incoming.send(Err(err.to_string()));
+                        return;
+                    }
+                }
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    let _ = socket.close(None);
+                    let _ = socket.write_pending();
+                    return;
+                }
+            }
+        }
+        match socket.read_message() {
+            Ok(Message::Text(data)) => {
+                if let Ok(message) = serde_json::from_str(&data) {
+                    if incoming.send(Ok(message)).is_err() {
+                        return;
+                    }
+                }
+            }
+            Ok(Message::Close(frame)) => {
+                let reason = frame
+                    .map(|e| e.reason.to_string())
+                    .unwrap_or_else(|| "connection closed".to_string());
+                let _ = incoming.send(Err(reason));
+                return;
+            }
+            // pings are answered by tungstenite
+            Ok(_) => {}
+            Err(tungstenite::Error::Io(err))
+                if matches!(
+                    err.kind(),
+                    std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut
+                ) => {}
+            Err(err) => {
+                let _ = incoming.send(Err(err.to_string()));
+                return;
+            }
+        }
+    }
+}
+
+impl Connection {
+    pub fn connect(remote: &RemoteWorld, story: &str) -> Result<Self> {
+        let mut request = remote.ws_url(story).into_client_request()?;
+        // secret is not a part of the url so it doesn't end up in logs
+        let protocols = protocol::ws_protocols(remote.secret.as_deref()).join(", ");
+        request
+            .headers_mut()
+            .insert(SEC_WEBSOCKET_PROTOCOL, HeaderValue::from_str(&protocols)?);
+        let default_port = match request.uri().scheme_str() {
+            Some("ws") => 80,
+            Some("wss") => 443,
+            _ => return Err(anyhow!("Only ws:// and wss:// urls are supported")),
+        };
+        let host = request
+            .uri()
+            .host()
+            .ok_or_else(|| anyhow!("Missing host in '{}'", remote.url))?;
+        let port = request.uri().port_u16().unwrap_or(default_port);
+        let stream = TcpStream::connect((host, port))?;
+        // reads can't block outgoing messages for long
+        stream.set_read_timeout(Some(POLL_INTERVAL))?;
+        // TLS is used for wss:// urls (certificates are checked against webpki roots)
+        let (socket, _) = tungstenite::client_tls(request, stream)
+            .map_err(|err| anyhow!("Failed to connect to '{}': {}", remote.url, err))?;
+
+        let (outgoing, outgoing_rx) = mpsc::channel();
+        let (incoming_tx, incoming) = mpsc::channel();
+        thread::spawn(move || handle_socket(socket, outgoing_rx, incoming_tx));
+        Ok(Self {
+            world_id: remote.world_id,
+            outgoing,
+            incoming,
+            notifications: vec![],
+        })
+    }
+
+    fn send(&self, message: &protocol::Message) -> Result<()> {
+        self.outgoing
+            .send(serde_json::to_string(message)?)
+            .map_err(|_| anyhow!("Connection closed"))
+    }
+
+    /// Sends the request and waits for its response
+    fn request(&mut self, request: protocol::RequestMessage) -> Result<protocol::ResponseMessage> {
+        let msg_id = request.msg_id();
+        self.send(&protocol::Message::Request(request))?;
+        let deadline = Instant::now() + REQUEST_TIMEOUT;
+        loop {
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            match self.incoming.recv_timeout(timeout) {
+                Ok(Ok(protocol::Message::Response(response))) => {
+                    if response.msg_id() == Some(msg_id) {
+                        if let protocol::ResponseMessage::Error(error) = response {
+                            return Err(anyhow!("Request failed: {:?}", error.code));
+                        }
+                        return Ok(response);
+                    }
+                }
+                Ok(Ok(protocol::Message::Notification(notification))) => {
+                    self.notifications.push(notification);
+                }
+                // requests are handled by the owner
+                Ok(Ok(protocol::Message::Request(_))) => {}
+                Ok(Err(reason)) => return Err(anyhow!("Connection closed: {}", reason)),
+                Err(RecvTimeoutError::Timeout) => return Err(anyhow!("Request timed out")),
+                Err(RecvTimeoutError::Disconnected) => return Err(anyhow!("Connection closed")),
+            }
+        }
+    }
+
+    /// Obtains the current data of the world from its owner
+    pub fn get_world(&mut self) -> Result<Value> {
+        let request = protocol::RequestMessage::GetWorld(protocol::GetWorldRequest {
+            msg_id: Uuid::new_v4(),
+            world_id: self.world_id,
+        });
+        match self.request(request)? {
+            protocol::ResponseMessage::GetWorld(response) => response
+                .world
+                .ok_or_else(|| anyhow!("World is not known to the owner")),
+            _ => Err(anyhow!("Unexpected response")),
+        }
+    }
+
+    /// Asks the owner to trigger the event
+    pub fn trigger_event(&mut self, event: Value) -> Result<bool> {
+        let request = protocol::RequestMessage::TriggerEvent(protocol::TriggerEventRequest {
+            msg_id: Uuid::new_v4(),
+            event,
+            world_id: self.world_id,
+        });
+        match self.request(request)? {
+            protocol::ResponseMessage::TriggerEvent(response) => Ok(response.success),
+            _ => Err(anyhow!("Unexpected response")),
+        }
+    }
+
+    /// Announces that the character joined the world
+    pub fn joined(&self, character: Value) -> Result<()> {
+        self.send(&protocol::Message::Notification(
+            protocol::NotificationMessage::Joined(protocol::JoinedNotification { character }),
+        ))
+    }
+
+    /// Notifications which arrived since the last call
+    pub fn notifications(&mut self) -> Result<Vec<protocol::NotificationMessage>> {
+        loop {
+            match self.incoming.try_recv() {
+                Ok(Ok(protocol::Message::Notification(notification))) => {
+                    self.notifications.push(notification)
+                }
+                Ok(Ok(_)) => {}
+                Ok(Err(reason)) => return Err(anyhow!("Connection closed: {}", reason)),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => return Err(anyhow!("Connection closed")),
+            }
+        }
+        Ok(std::mem::take(&mut self.notifications))
+    }
+}
@@ -0,0 +1,216 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    fs,
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::Path,
+};
+use uuid::Uuid;
+
+use super::backend;
+use crate::{Event, Narrator, World};
+
+/// Line of a transcript file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Entry {
+    /// Recording started (or the world was reset or loaded)
+    Start {
+        story: String,
+        lang: String,
+        world_id: Uuid,
+        world: Value,
+    },
+    /// Event was triggered
+    Event {
+        event: Value,
+        success: bool,
+        event_count: usize,
+        /// World after the event (not known for remote worlds)
+        #[serde(default)]
+        world: Option<Value>,
+    },
+}
+
+/// Appends entries to a transcript file
+pub struct Recorder {
+    story: String,
+    writer: BufWriter<fs::File>,
+}
+
+impl Recorder {
+    pub fn create(path: &Path, story: &str) -> Result<Self> {
+        Ok(Self {
+            story: story.to_string(),
+            writer: BufWriter::new(fs::File::create(path)?),
+        })
+    }
+
+    fn write(&mut self, entry: &Entry) -> Result<()> {
+        serde_json::to_writer(&mut self.writer, entry)?;
+        self.writer.write_all(b"\n")?;
+        // keep the transcript when the app crashes
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    /// Records the state which the following events are applied to
+    pub fn start(&mut self, world: &dyn World) -> Result<()> {
+        self.write(&Entry::Start {
+            story: self.story.clone(),
+            lang: world.lang().to_string(),
+            world_id: *world.id(),
+            world: world.dump(),
+        })
+    }
+
+    pub fn event(&mut self, event: &dyn Event, success: bool, world: &dyn World) -> Result<()> {
+        self.write(&Entry::Event {
+            event: event.dump(),
+            success,
+            event_count: world.event_count(),
+            world: Some(world.dump()),
+        })
+    }
+
+    /// Records event from another device of a remote world
+    pub fn remote_event(&mut self, event: Value, event_count: usize) -> Result<()> {
+        self.write(&Entry::Event {
+            event,
+            success: true,
+            event_count,
+            world: None,
+        })
+    }
+}
+
+pub fn read(path: &Path) -> Result<Vec<Entry>> {
+    let reader = BufReader::new(fs::File::open(path)?);
+    let mut entries = vec![];
+    for (idx, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        entries.push(
+            serde_json::from_str(&line)
+                .map_err(|err| anyhow!("Invalid entry on line {}: {}", idx + 1, err))?,
+        );
+    }
+    Ok(entries)
+}
+
+/// First place where the replayed world differs from the transcript
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Divergence {
+    /// Index of the transcript entry
+    pub entry: usize,
+    pub event: Value,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ReplayReport {
+    pub entries: usize,
+    /// Events which were replayed without a divergence
+    pub replayed: usize,
+    pub divergence: Option<Divergence>,
+}
+
+/// Re-executes the transcript using a fresh world
+pub fn replay(
+    entries: &[Entry],
+    story: &str,
+    world: &mut dyn World,
+    narrator: &dyn Narrator,
+) -> Result<ReplayReport> {
+    let mut report = ReplayReport {
+        entries: entries.len(),
+        replayed: 0,
+        divergence: None,
+    };
+    let mut started = false;
+    for (idx, entry) in entries.iter().enumerate() {
+        let diverged = |event: &Value, reason: String| Divergence {
+            entry: idx,
+            event: event.clone(),
+            reason,
+        };
+        match entry {
+            Entry::Start {
+                story: recorded,
+                lang,
+                world_id,
+                world: data,
+            } => {
+                if recorded != story {
+                    return Err(anyhow!(
+                        "Transcript belongs to story '{}' not '{}'",
+                        recorded,
+                        story
+                    ));
+                }
+                if !world.set_lang(lang) {
+                    return Err(anyhow!("Unsupported language '{}'", lang));
+                }
+                world.clean();
+                world.set_id(*world_id);
+                world.setup(false);
+                world.load(data.clone())?;
+                started = true;
+            }
+            Entry::Event { .. } if !started => {
+                return Err(anyhow!("Transcript doesn't start with the initial world"));
+            }
+            Entry::Event {
+                event: data,
+                success,
+                event_count,
+                world: expected,
+            } => {
+                let mut event = if let Some(event) = narrator.parse_event(world, data.clone()) {
+                    event
+                } else {
+                    report.divergence = Some(diverged(data, "unknown event".to_string()));
+                    break;
+                };
+                let can_be_triggered = event.can_be_triggered(world);
+                if can_be_triggered != *success {
+                    let reason = if *success {
+                        "event can't be triggered"
+                    } else {
+                        "event was expected to fail"
+                    };
+                    report.divergence = Some(diverged(data, reason.to_string()));
+                    break;
+                }
+                if can_be_triggered {
+                    event.trigger(world);
+                }
+                if world.event_count() != *event_count {
+                    report.divergence = Some(diverged(
+                        data,
+                        format!(
+                            "event count {} doesn't match {}",
+                            world.event_count(),
+                            event_count
+                        ),
+                    ));
+                    break;
+                }
+                if let Some(expected) = expected {
+                    if let Some(change) = backend::diff(expected, &world.dump()).first() {
+                        report.divergence = Some(diverged(
+                            data,
+                            format!("world differs at '{}'", change.path),
+                        ));
+                        break;
+                    }
+                }
+                report.replayed += 1;
+            }
+        }
+    }
+    Ok(report)
+}
@@ -1,9 +1,84 @@
-use crate::{ItemState, World};
+use crate::{
+    graph::{Link, Node},
+    ItemState, World,
+};
 use anyhow::{anyhow, Result};
+use serde::Serialize;
 use std::{collections::HashSet, fmt, ops, rc::Rc};
 
 pub trait Check: fmt::Debug {
     fn check(&self, world: &dyn World) -> Result<bool>;
+
+    /// Scenes, characters and items which the check depends on (see `graph`)
+    fn links(&self) -> Vec<Link> {
+        vec![]
+    }
+
+    /// Why the check doesn't pass (called only when it fails)
+    fn explain(&self, _world: &dyn World) -> Option<String> {
+        None
+    }
+}
+
+fn describe_state(state: &ItemState) -> String {
+    match state {
+        ItemState::Owned(character) => format!("owned by {}", character),
+        ItemState::InScene(scene) => format!("in {}", scene),
+        ItemState::Unassigned => "unassigned".to_string(),
+    }
+}
+
+/// Evaluated condition tree
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Diagnosis {
+    pub check: String,
+    pub passed: bool,
+    pub explanation: Option<String>,
+    pub children: Vec<Diagnosis>,
+}
+
+impl Diagnosis {
+    /// Explanations of the failed checks
+    pub fn failures(&self) -> Vec<String> {
+        match (self.passed, self.children.is_empty()) {
+            (true, _) => vec![],
+            (false, true) => vec![format!(
+                "{}: {}",
+                self.check,
+                self.explanation.as_deref().unwrap_or("failed")
+            )],
+            // inner condition of Not passed
+            (false, false) if self.check == "Not" => {
+                vec![format!("{} passed", self.children[0].check)]
+            }
+            (false, false) => self.children.iter().flat_map(|e| e.failures()).collect(),
+        }
+    }
+
+    fn fmt_indented(&self, f: &mut fmt::Formatter<'_>, indent: usize) -> fmt::Result {
+        write!(
+            f,
+            "{:indent$}{} {}",
+            "",
+            if self.passed { "pass" } else { "FAIL" },
+            self.check,
+            indent = indent
+        )?;
+        if let Some(explanation) = self.explanation.as_ref() {
+            write!(f, ": {}", explanation)?;
+        }
+        writeln!(f)?;
+        for child in &self.children {
+            child.fmt_indented(f, indent + 2)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Diagnosis {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.fmt_indented(f, 0)
+    }
 }
 
 pub enum Condition {
@@ -17,6 +92,46 @@ impl Condition {
     pub fn new(check: impl Check + 'static) -> Self {
         Self::Check(Rc::new(check))
     }
+
+    /// Evaluates every sub-check and explains the failed ones
+    pub fn diagnose(&self, world: &dyn World) -> Diagnosis {
+        let combined = |check: &str, children: Vec<Diagnosis>, passed: bool| Diagnosis {
+            check: check.to_string(),
+            passed,
+            explanation: None,
+            children,
+        };
+        match self {
+            Self::Check(check) => {
+                let (passed, explanation) = match check.check(world) {
+                    Ok(true) => (true, None),
+                    Ok(false) => (false, check.explain(world)),
+                    Err(err) => (false, Some(err.to_string())),
+                };
+                Diagnosis {
+                    check: format!("{:?}", check),
+                    passed,
+                    explanation,
+                    children: vec![],
+                }
+            }
+            Self::Not(cond) => {
+                let inner = cond.diagnose(world);
+                let passed = !inner.passed;
+                combined("Not", vec![inner], passed)
+            }
+            Self::And(cond1, cond2) => {
+                let children = vec![cond1.diagnose(world), cond2.diagnose(world)];
+                let passed = children.iter().all(|e| e.passed);
+                combined("And", children, passed)
+            }
+            Self::Or(cond1, cond2) => {
+                let children = vec![cond1.diagnose(world), cond2.diagnose(world)];
+                let passed = children.iter().any(|e| e.passed);
+                combined("Or", children, passed)
+            }
+        }
+    }
 }
 
 impl Default for Condition {
@@ -35,6 +150,31 @@ impl Check for Condition {
             Self::Or(cond1, cond2) => Ok(cond1.check(world)? || cond2.check(world)?),
         }
     }
+
+    fn explain(&self, world: &dyn World) -> Option<String> {
+        let failures = self.diagnose(world).failures();
+        if failures.is_empty() {
+            None
+        } else {
+            Some(failures.join("; "))
+        }
+    }
+
+    fn links(&self) -> Vec<Link> {
+        match self {
+            Self::Check(check) => check.links(),
+            Self::Not(cond) => cond
+                .links()
+                .into_iter()
+                .map(|e| Link::new(e.node, format!("not {}", e.label)))
+                .collect(),
+            Self::And(cond1, cond2) | Self::Or(cond1, cond2) => {
+                let mut links = cond1.links();
+                links.extend(cond2.links());
+                links
+            }
+        }
+    }
 }
 
 impl fmt::Debug for Condition {
@@ -159,6 +299,45 @@ impl Check for SameSceneCheck {
             Ok(false)
         }
     }
+
+    fn explain(&self, world: &dyn World) -> Option<String> {
+        let characters = self.characters.iter().map(|e| {
+            let scene = world
+                .characters()
+                .get(e.as_str())
+                .and_then(|e| e.scene().clone());
+            (e, scene)
+        });
+        let items = self.items.iter().map(|e| {
+            let scene = world.items().get(e.as_str()).and_then(|e| match e.state() {
+                ItemState::InScene(scene) => Some(scene.clone()),
+                _ => None,
+            });
+            (e, scene)
+        });
+        Some(
+            characters
+                .chain(items)
+                .map(|(name, scene)| {
+                    if let Some(scene) = scene {
+                        format!("{} is in {}", name, scene)
+                    } else {
+                        format!("{} is not in any scene", name)
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join(", "),
+        )
+    }
+
+    fn links(&self) -> Vec<Link> {
+        self.characters
+            .iter()
+            .map(|e| Node::Character(e.clone()))
+            .chain(self.items.iter().map(|e| Node::Item(e.clone())))
+            .map(|e| Link::new(e, "same scene"))
+            .collect()
+    }
 }
 
 impl From<SameSceneCheck> for Condition {
@@ -198,6 +377,26 @@ impl Check for HasItemCheck {
             Ok(false)
         }
     }
+
+    fn explain(&self, world: &dyn World) -> Option<String> {
+        Some(if let Some(item) = world.items().get(&self.item) {
+            format!(
+                "{} does not own {} (it is {})",
+                self.character,
+                self.item,
+                describe_state(item.state())
+            )
+        } else {
+            format!("item {} not found", self.item)
+        })
+    }
+
+    fn links(&self) -> Vec<Link> {
+        vec![Link::new(
+            Node::Item(self.item.clone()),
+            format!("owned by {}", self.character),
+        )]
+    }
 }
 
 impl From<HasItemCheck> for Condition {
@@ -236,6 +435,33 @@ impl Check for CharacterInSceneCheck {
             },
         )
     }
+
+    fn explain(&self, world: &dyn World) -> Option<String> {
+        let expected = self.scene.as_deref().unwrap_or("no scene");
+        Some(
+            if let Some(character) = world.characters().get(&self.character) {
+                format!(
+                    "{} is in {} not {}",
+                    self.character,
+                    character.scene().as_deref().unwrap_or("no scene"),
+                    expected
+                )
+            } else {
+                format!("character {} not found", self.character)
+            },
+        )
+    }
+
+    fn links(&self) -> Vec<Link> {
+        vec![if let Some(scene) = self.scene.as_ref() {
+            Link::new(
+                Node::Scene(scene.clone()),
+                format!("{} in scene", self.character),
+            )
+        } else {
+            Link::new(Node::Character(self.character.clone()), "in no scene")
+        }]
+    }
 }
 
 impl From<CharacterInSceneCheck> for Condition {
@@ -280,6 +506,25 @@ impl Check for CanGiveCheck {
             ))
         .check(world)
     }
+
+    fn explain(&self, world: &dyn World) -> Option<String> {
+        (HasItemCheck::cond(self.from_character.clone(), self.item.clone())
+            & SameSceneCheck::cond(
+                vec![self.from_character.clone(), self.to_character.clone()],
+                vec![],
+            ))
+        .explain(world)
+    }
+
+    fn links(&self) -> Vec<Link> {
+        vec![
+            Link::new(
+                Node::Item(self.item.clone()),
+                format!("owned by {}", self.from_character),
+            ),
+            Link::new(Node::Character(self.to_character.clone()), "same scene"),
+        ]
+    }
 }
 
 impl From<CanGiveCheck> for Condition {
@@ -316,6 +561,33 @@ impl Check for AllItemsWithTagInStateCheck {
             .filter(|e| e.get_tags().iter().any(|t| self.tags.contains(t)))
             .all(|e| e.state() == &self.state))
     }
+
+    fn explain(&self, world: &dyn World) -> Option<String> {
+        let mut items: Vec<String> = world
+            .items()
+            .values()
+            .filter(|e| e.get_tags().iter().any(|t| self.tags.contains(t)))
+            .filter(|e| e.state() != &self.state)
+            .map(|e| format!("{} is {}", e.name(), describe_state(e.state())))
+            .collect();
+        items.sort();
+        Some(format!(
+            "{} (expected {})",
+            items.join(", "),
+            describe_state(&self.state)
+        ))
+    }
+
+    fn links(&self) -> Vec<Link> {
+        let label = format!("all {} items", self.tags.join(", "));
+        match &self.state {
+            ItemState::Owned(character) => {
+                vec![Link::new(Node::Character(character.clone()), label)]
+            }
+            ItemState::InScene(scene) => vec![Link::new(Node::Scene(scene.clone()), label)],
+            ItemState::Unassigned => vec![],
+        }
+    }
 }
 
 impl From<AllItemsWithTagInStateCheck> for Condition {
@@ -354,6 +626,21 @@ impl Check for SceneDialogCheck {
             .ok_or_else(|| anyhow!("Scene {} doesn't have dialogs", &self.scene))?
             == self.dialog)
     }
+
+    fn explain(&self, world: &dyn World) -> Option<String> {
+        let dialog = world.scenes().get(&self.scene)?.dialog()?;
+        Some(format!(
+            "scene {} is at dialog {} not {}",
+            self.scene, dialog, self.dialog
+        ))
+    }
+
+    fn links(&self) -> Vec<Link> {
+        vec![Link::new(
+            Node::Scene(self.scene.clone()),
+            format!("dialog {}", self.dialog),
+        )]
+    }
 }
 
 impl From<SceneDialogCheck> for Condition {
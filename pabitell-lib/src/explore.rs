@@ -0,0 +1,266 @@
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    fmt::Write,
+    str::FromStr,
+};
+
+use crate::{Narrator, World};
+
+/// Limits of the exploration
+#[derive(Debug, Clone)]
+pub struct ExploreOptions {
+    /// Maximal number of events from the initial state
+    pub max_depth: usize,
+    /// Exploration stops when this number of states is found
+    pub max_states: usize,
+    /// Keys which are not part of the canonical state
+    /// (e.g. counters which differ even when the world is the same)
+    pub ignored_keys: Vec<String>,
+}
+
+impl Default for ExploreOptions {
+    fn default() -> Self {
+        Self {
+            max_depth: 10,
+            max_states: 10_000,
+            ignored_keys: vec!["event_count".to_string(), "last_event".to_string()],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct State {
+    pub id: usize,
+    /// Number of events on the shortest path from the initial state
+    pub depth: usize,
+    pub finished: bool,
+    /// Events of the state were tried (false when a limit was reached)
+    pub expanded: bool,
+    pub world: Value,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Transition {
+    pub from: usize,
+    pub to: usize,
+    pub event: Value,
+    pub initiator: String,
+    pub name: String,
+}
+
+/// Reachable states of a world and events which lead between them
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct StateGraph {
+    pub states: Vec<State>,
+    pub transitions: Vec<Transition>,
+    /// Some states were not expanded because of the limits
+    pub truncated: bool,
+}
+
+/// Output format of the state graph
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Dot,
+    Json,
+}
+
+impl FromStr for Format {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "dot" => Ok(Self::Dot),
+            "json" => Ok(Self::Json),
+            e => Err(anyhow!("Unknown state graph format '{}'", e)),
+        }
+    }
+}
+
+/// Dump of the world without the ignored keys
+pub fn canonical(value: &Value, ignored_keys: &[String]) -> Value {
+    match value {
+        Value::Object(map) => Value::Object(
+            map.iter()
+                .filter(|(k, _)| !ignored_keys.contains(k))
+                .map(|(k, v)| (k.clone(), canonical(v, ignored_keys)))
+                .collect(),
+        ),
+        Value::Array(values) => {
+            Value::Array(values.iter().map(|e| canonical(e, ignored_keys)).collect())
+        }
+        e => e.clone(),
+    }
+}
+
+fn restore(world: &mut dyn World, data: &Value) -> Result<()> {
+    world.reset();
+    world.load(data.clone())
+}
+
+/// Triggers all available events breadth first starting with the current world
+///
+/// the world is left in an arbitrary explored state
+pub fn explore(
+    world: &mut dyn World,
+    narrator: &dyn Narrator,
+    options: &ExploreOptions,
+) -> Result<StateGraph> {
+    let mut graph = StateGraph::default();
+    // canonical state -> state id
+    let mut known: HashMap<String, usize> = HashMap::new();
+    let mut queue = VecDeque::new();
+
+    let initial = world.dump();
+    known.insert(canonical(&initial, &options.ignored_keys).to_string(), 0);
+    graph.states.push(State {
+        id: 0,
+        depth: 0,
+        finished: world.finished(),
+        expanded: false,
+        world: initial,
+    });
+    queue.push_back(0);
+
+    while let Some(id) = queue.pop_front() {
+        let (depth, data) = {
+            let state = &graph.states[id];
+            if state.finished {
+                continue;
+            }
+            if state.depth >= options.max_depth {
+                graph.truncated = true;
+                continue;
+            }
+            (state.depth, state.world.clone())
+        };
+        restore(world, &data)?;
+        let count = narrator.available_events(world).len();
+        for idx in 0..count {
+            if graph.states.len() >= options.max_states {
+                graph.truncated = true;
+                return Ok(graph);
+            }
+            restore(world, &data)?;
+            // events may hold data of the world so they are obtained again
+            let mut event = narrator
+                .available_events(world)
+                .into_iter()
+                .nth(idx)
+                .ok_or_else(|| anyhow!("Available events differ for the same state"))?;
+            event.trigger(world);
+            let dump = world.dump();
+            let key = canonical(&dump, &options.ignored_keys).to_string();
+            let to = if let Some(to) = known.get(&key) {
+                *to
+            } else {
+                let to = graph.states.len();
+                known.insert(key, to);
+                graph.states.push(State {
+                    id: to,
+                    depth: depth + 1,
+                    finished: world.finished(),
+                    expanded: false,
+                    world: dump,
+                });
+                queue.push_back(to);
+                to
+            };
+            graph.transitions.push(Transition {
+                from: id,
+                to,
+                event: event.dump(),
+                initiator: event.initiator(),
+                name: event.name().to_string(),
+            });
+        }
+        graph.states[id].expanded = true;
+    }
+    Ok(graph)
+}
+
+impl StateGraph {
+    /// States where nothing can be triggered and the story is not finished
+    pub fn dead_ends(&self) -> HashSet<usize> {
+        let with_events: HashSet<usize> = self.transitions.iter().map(|e| e.from).collect();
+        self.states
+            .iter()
+            .filter(|e| e.expanded && !e.finished && !with_events.contains(&e.id))
+            .map(|e| e.id)
+            .collect()
+    }
+
+    pub fn dot(&self) -> String {
+        let dead_ends = self.dead_ends();
+        let mut res = String::from("digraph states {\n");
+        for state in &self.states {
+            let color = if state.finished {
+                "green"
+            } else if dead_ends.contains(&state.id) {
+                "red"
+            } else if !state.expanded {
+                "gray"
+            } else {
+                "black"
+            };
+            writeln!(
+                res,
+                "  s{} [label=\"{}\", color={}];",
+                state.id, state.id, color
+            )
+            .unwrap();
+        }
+        for transition in &self.transitions {
+            writeln!(
+                res,
+                "  s{} -> s{} [label=\"{}: {}\"];",
+                transition.from,
+                transition.to,
+                transition.initiator.replace('"', "\\\""),
+                transition.name.replace('"', "\\\"")
+            )
+            .unwrap();
+        }
+        res.push_str("}\n");
+        res
+    }
+
+    pub fn render(&self, format: Format) -> Result<String> {
+        match format {
+            Format::Dot => Ok(self.dot()),
+            Format::Json => {
+                let mut dead_ends: Vec<usize> = self.dead_ends().into_iter().collect();
+                dead_ends.sort_unstable();
+                let mut value = serde_json::to_value(self)?;
+                value["dead_ends"] = json!(dead_ends);
+                Ok(serde_json::to_string_pretty(&value)? + "\n")
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::canonical;
+    use serde_json::json;
+
+    #[test]
+    fn canonical_state() {
+        let ignored = vec!["event_count".to_string(), "last_event".to_string()];
+        let first = json!({
+            "items": {"cake": {"state": "kitchen", "last_event": 1}},
+            "event_count": 3
+        });
+        let second = json!({
+            "items": {"cake": {"state": "kitchen", "last_event": 2}},
+            "event_count": 4
+        });
+        assert_eq!(canonical(&first, &ignored), canonical(&second, &ignored));
+        assert_eq!(
+            canonical(&first, &ignored),
+            json!({"items": {"cake": {"state": "kitchen"}}})
+        );
+    }
+}
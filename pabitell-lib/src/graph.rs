@@ -0,0 +1,285 @@
+use anyhow::{anyhow, Result};
+use std::{collections::BTreeSet, fmt::Write, str::FromStr};
+
+use crate::{conditions::Check, Narrator, World};
+
+/// Node of the story graph
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Node {
+    Scene(String),
+    Character(String),
+    Item(String),
+    /// Event is identified by its position in `Narrator::all_events`
+    Event(usize, String),
+}
+
+impl Node {
+    fn id(&self) -> String {
+        let (prefix, name) = match self {
+            Self::Scene(name) => ("scene", name.clone()),
+            Self::Character(name) => ("character", name.clone()),
+            Self::Item(name) => ("item", name.clone()),
+            Self::Event(idx, _) => ("event", idx.to_string()),
+        };
+        let name: String = name
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect();
+        format!("{}_{}", prefix, name)
+    }
+
+    fn label(&self) -> &str {
+        match self {
+            Self::Scene(name) | Self::Character(name) | Self::Item(name) => name,
+            Self::Event(_, name) => name,
+        }
+    }
+}
+
+/// Entity which a check or a change refers to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Link {
+    pub node: Node,
+    pub label: String,
+}
+
+impl Link {
+    pub fn new<S>(node: Node, label: S) -> Self
+    where
+        S: ToString,
+    {
+        Self {
+            node,
+            label: label.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EdgeKind {
+    /// Character can trigger the event
+    Initiates,
+    /// Event requires the node (derived from the condition)
+    Requires,
+    /// Event moves something to the node (derived from the world updates)
+    Changes,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Edge {
+    pub from: Node,
+    pub to: Node,
+    pub label: String,
+    pub kind: EdgeKind,
+}
+
+/// Output format of the graph
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Dot,
+    Mermaid,
+}
+
+impl FromStr for Format {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "dot" => Ok(Self::Dot),
+            "mermaid" => Ok(Self::Mermaid),
+            e => Err(anyhow!("Unknown graph format '{}'", e)),
+        }
+    }
+}
+
+/// Scenes, characters, items and events of a story and how they relate
+#[derive(Debug, Clone, Default)]
+pub struct StoryGraph {
+    pub name: String,
+    pub nodes: BTreeSet<Node>,
+    pub edges: Vec<Edge>,
+}
+
+impl StoryGraph {
+    pub fn new(world: &dyn World, narrator: &dyn Narrator) -> Self {
+        let mut graph = Self {
+            name: world.name().to_string(),
+            ..Default::default()
+        };
+        for name in world.scenes().keys() {
+            graph.nodes.insert(Node::Scene(name.clone()));
+        }
+        for name in world.characters().keys() {
+            graph.nodes.insert(Node::Character(name.clone()));
+        }
+        for name in world.items().keys() {
+            graph.nodes.insert(Node::Item(name.clone()));
+        }
+
+        for (idx, event) in narrator.all_events(world).iter().enumerate() {
+            let node = Node::Event(idx, event.name().to_string());
+            graph.nodes.insert(node.clone());
+            graph.edges.push(Edge {
+                from: Node::Character(event.initiator()),
+                to: node.clone(),
+                label: String::new(),
+                kind: EdgeKind::Initiates,
+            });
+            for link in event.get_condition().links() {
+                graph.nodes.insert(link.node.clone());
+                graph.edges.push(Edge {
+                    from: link.node,
+                    to: node.clone(),
+                    label: link.label,
+                    kind: EdgeKind::Requires,
+                });
+            }
+            for link in event.get_world_updates().iter().flat_map(|e| e.links()) {
+                graph.nodes.insert(link.node.clone());
+                graph.edges.push(Edge {
+                    from: node.clone(),
+                    to: link.node,
+                    label: link.label,
+                    kind: EdgeKind::Changes,
+                });
+            }
+        }
+        graph
+    }
+
+    pub fn dot(&self) -> String {
+        let quote = |text: &str| format!("\"{}\"", text.replace('"', "\\\""));
+        let mut res = String::new();
+        writeln!(res, "digraph {} {{", quote(&self.name)).unwrap();
+        writeln!(res, "  rankdir=LR;").unwrap();
+        for node in &self.nodes {
+            let shape = match node {
+                Node::Scene(_) => "box",
+                Node::Character(_) => "ellipse",
+                Node::Item(_) => "note",
+                Node::Event(_, _) => "diamond",
+            };
+            writeln!(
+                res,
+                "  {} [label={}, shape={}];",
+                node.id(),
+                quote(node.label()),
+                shape
+            )
+            .unwrap();
+        }
+        for edge in &self.edges {
+            let style = match edge.kind {
+                EdgeKind::Initiates => "bold",
+                EdgeKind::Requires => "dashed",
+                EdgeKind::Changes => "solid",
+            };
+            writeln!(
+                res,
+                "  {} -> {} [label={}, style={}];",
+                edge.from.id(),
+                edge.to.id(),
+                quote(&edge.label),
+                style
+            )
+            .unwrap();
+        }
+        res.push_str("}\n");
+        res
+    }
+
+    pub fn mermaid(&self) -> String {
+        let quote = |text: &str| format!("\"{}\"", text.replace('"', "#quot;"));
+        let mut res = String::from("flowchart LR\n");
+        for node in &self.nodes {
+            let label = quote(node.label());
+            let shape = match node {
+                Node::Scene(_) => format!("[{}]", label),
+                Node::Character(_) => format!("([{}])", label),
+                Node::Item(_) => format!(">{}]", label),
+                Node::Event(_, _) => format!("{{{{{}}}}}", label),
+            };
+            writeln!(res, "  {}{}", node.id(), shape).unwrap();
+        }
+        for edge in &self.edges {
+            let arrow = match edge.kind {
+                EdgeKind::Initiates => "==>",
+                EdgeKind::Requires => "-.->",
+                EdgeKind::Changes => "-->",
+            };
+            if edge.label.is_empty() {
+                writeln!(res, "  {} {} {}", edge.from.id(), arrow, edge.to.id()).unwrap();
+            } else {
+                writeln!(
+                    res,
+                    "  {} {}|{}| {}",
+                    edge.from.id(),
+                    arrow,
+                    quote(&edge.label),
+                    edge.to.id()
+                )
+                .unwrap();
+            }
+        }
+        res
+    }
+
+    pub fn render(&self, format: Format) -> String {
+        match format {
+            Format::Dot => self.dot(),
+            Format::Mermaid => self.mermaid(),
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::{Edge, EdgeKind, Link, Node, StoryGraph};
+    use crate::{
+        conditions::{CharacterInSceneCheck, Check, HasItemCheck},
+        updates::{AssignItemChange, Change},
+        ItemState,
+    };
+
+    #[test]
+    fn condition_links() {
+        let condition = HasItemCheck::cond("doggie".into(), "cake".into())
+            & !CharacterInSceneCheck::cond("kitie".into(), Some("kitchen".into()));
+        assert_eq!(
+            condition.links(),
+            vec![
+                Link::new(Node::Item("cake".into()), "owned by doggie"),
+                Link::new(Node::Scene("kitchen".into()), "not kitie in scene"),
+            ]
+        );
+        assert_eq!(
+            AssignItemChange::new("cake".into(), ItemState::InScene("kitchen".into())).links(),
+            vec![Link::new(Node::Scene("kitchen".into()), "cake")]
+        );
+    }
+
+    #[test]
+    fn render() {
+        let mut graph = StoryGraph {
+            name: "test".into(),
+            ..Default::default()
+        };
+        let event = Node::Event(0, "pick".into());
+        graph.nodes.insert(Node::Character("doggie".into()));
+        graph.nodes.insert(event.clone());
+        graph.edges.push(Edge {
+            from: event,
+            to: Node::Character("doggie".into()),
+            label: "cake".into(),
+            kind: EdgeKind::Changes,
+        });
+        assert_eq!(
+            graph.dot(),
+            "digraph \"test\" {\n  rankdir=LR;\n  character_doggie [label=\"doggie\", shape=ellipse];\n  event_0 [label=\"pick\", shape=diamond];\n  event_0 -> character_doggie [label=\"cake\", style=solid];\n}\n"
+        );
+        assert_eq!(
+            graph.mermaid(),
+            "flowchart LR\n  character_doggie([\"doggie\"])\n  event_0{{\"pick\"}}\n  event_0 -->|\"cake\"| character_doggie\n"
+        );
+    }
+}
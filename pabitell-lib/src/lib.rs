@@ -3,7 +3,10 @@ pub mod cli;
 pub mod conditions;
 pub mod data;
 pub mod events;
+pub mod explore;
+pub mod graph;
 pub mod items;
+pub mod lint;
 pub mod protocol;
 pub mod scenes;
 pub mod translations;
@@ -778,4 +781,34 @@ pub mod test {
 
         assert!(world.build().is_ok());
     }
+
+    #[test]
+    fn diagnosis() {
+        let world = TestWorldBuilder::default()
+            .character(Box::new(TestCharacter::default()))
+            .item(Box::new(TestItem::default()))
+            .scene(Box::new(TestScene::default()))
+            .build()
+            .unwrap();
+
+        let condition = conditions::AlwaysCheck::cond()
+            & conditions::HasItemCheck::cond("test_character".into(), "test_item".into());
+        let diagnosis = condition.diagnose(&world);
+        assert!(!diagnosis.passed);
+        assert!(diagnosis.children[0].passed);
+        assert_eq!(
+            diagnosis.children[1].explanation.as_deref(),
+            Some("test_character does not own test_item (it is unassigned)")
+        );
+        assert_eq!(
+            diagnosis.to_string(),
+            "FAIL And\n  pass Always\n  FAIL HasItem { character: \"test_character\", item: \"test_item\" }: test_character does not own test_item (it is unassigned)\n"
+        );
+
+        let condition = !conditions::CharacterInSceneCheck::cond("test_character".into(), None);
+        assert_eq!(
+            condition.diagnose(&world).failures(),
+            vec!["CharacterInScene { character: \"test_character\", scene: None } passed"]
+        );
+    }
 }
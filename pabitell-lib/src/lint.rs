@@ -0,0 +1,421 @@
+use anyhow::{anyhow, Result};
+use fluent::FluentArgs;
+use fluent_syntax::{
+    ast::{Entry, Expression, InlineExpression, Pattern, PatternElement},
+    parser,
+};
+use serde::Serialize;
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+};
+use uuid::Uuid;
+
+use crate::{
+    explore::{self, ExploreOptions},
+    Character, Clean, Dumpable, Item, Named, Narrator, Scene, World,
+};
+
+/// Message which was requested by the world
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Request {
+    /// Message was not found (the id was returned instead)
+    pub missing: bool,
+    /// Names of the arguments passed to the message
+    pub args: BTreeSet<String>,
+}
+
+/// Requested messages per language
+pub type Requests = BTreeMap<String, BTreeMap<String, Request>>;
+
+/// Messages defined in fluent files of a language
+#[derive(Debug, Clone, Default)]
+pub struct Messages {
+    /// Message id -> variables used in the message
+    pub variables: HashMap<String, BTreeSet<String>>,
+    /// Messages which are referenced from other messages
+    pub references: HashSet<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Issue {
+    /// Message is requested but not translated
+    Missing { lang: String, id: String },
+    /// Message is translated but never requested
+    Unused { lang: String, id: String },
+    /// Message uses variables which are not passed to it
+    MissingArguments {
+        lang: String,
+        id: String,
+        missing: BTreeSet<String>,
+    },
+    /// Arguments passed to the message are not used by it
+    /// (this is fine in fluent so it is reported only on demand)
+    UnusedArguments {
+        lang: String,
+        id: String,
+        unused: BTreeSet<String>,
+    },
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Report {
+    /// Number of distinct message ids which were requested
+    pub requested: usize,
+    /// Not all world states were checked because of the exploration limits
+    pub truncated: bool,
+    pub issues: Vec<Issue>,
+}
+
+fn inline_variables(expression: &InlineExpression<&str>, messages: &mut Messages) -> Vec<String> {
+    match expression {
+        InlineExpression::VariableReference { id } => vec![id.name.to_string()],
+        InlineExpression::FunctionReference { arguments, .. } => arguments
+            .positional
+            .iter()
+            .chain(arguments.named.iter().map(|e| &e.value))
+            .flat_map(|e| inline_variables(e, messages))
+            .collect(),
+        InlineExpression::MessageReference { id, .. } => {
+            messages.references.insert(id.name.to_string());
+            vec![]
+        }
+        InlineExpression::Placeable { expression } => expression_variables(expression, messages),
+        _ => vec![],
+    }
+}
+
+fn expression_variables(expression: &Expression<&str>, messages: &mut Messages) -> Vec<String> {
+    match expression {
+        Expression::Inline(inline) => inline_variables(inline, messages),
+        Expression::Select { selector, variants } => {
+            let mut res = inline_variables(selector, messages);
+            for variant in variants {
+                res.extend(pattern_variables(&variant.value, messages));
+            }
+            res
+        }
+    }
+}
+
+fn pattern_variables(pattern: &Pattern<&str>, messages: &mut Messages) -> Vec<String> {
+    pattern
+        .elements
+        .iter()
+        .flat_map(|e| match e {
+            PatternElement::TextElement { .. } => vec![],
+            PatternElement::Placeable { expression } => expression_variables(expression, messages),
+        })
+        .collect()
+}
+
+impl Messages {
+    /// Adds messages of a fluent file
+    pub fn parse(&mut self, source: &str) -> Result<()> {
+        let resource = parser::parse(source).map_err(|(_, errors)| {
+            anyhow!(
+                "Failed to parse fluent file: {}",
+                errors
+                    .iter()
+                    .map(|e| format!("{:?}", e.kind))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )
+        })?;
+        for entry in resource.body {
+            if let Entry::Message(message) = entry {
+                let mut variables = message
+                    .value
+                    .as_ref()
+                    .map(|e| pattern_variables(e, self))
+                    .unwrap_or_default();
+                for attribute in &message.attributes {
+                    variables.extend(pattern_variables(&attribute.value, self));
+                }
+                self.variables
+                    .insert(message.id.name.to_string(), variables.into_iter().collect());
+            }
+        }
+        Ok(())
+    }
+}
+
+/// World which records the requested messages
+struct RecordingWorld<'a> {
+    world: &'a mut dyn World,
+    requests: RefCell<BTreeMap<String, Request>>,
+}
+
+impl Named for RecordingWorld<'_> {
+    fn name(&self) -> &'static str {
+        self.world.name()
+    }
+}
+
+impl Dumpable for RecordingWorld<'_> {
+    fn dump(&self) -> serde_json::Value {
+        self.world.dump()
+    }
+    fn load(&mut self, data: serde_json::Value) -> Result<()> {
+        self.world.load(data)
+    }
+}
+
+impl Clean for RecordingWorld<'_> {
+    fn clean(&mut self) {
+        self.world.clean()
+    }
+}
+
+impl World for RecordingWorld<'_> {
+    fn available_languages(&self) -> Vec<String> {
+        self.world.available_languages()
+    }
+    fn lang(&self) -> &str {
+        self.world.lang()
+    }
+    fn set_lang(&mut self, lang: &str) -> bool {
+        self.world.set_lang(lang)
+    }
+    fn description(&self) -> Box<dyn crate::Description> {
+        self.world.description()
+    }
+    fn scenes(&self) -> &HashMap<String, Box<dyn Scene>> {
+        self.world.scenes()
+    }
+    fn scenes_mut(&mut self) -> &mut HashMap<String, Box<dyn Scene>> {
+        self.world.scenes_mut()
+    }
+    fn characters(&self) -> &HashMap<String, Box<dyn Character>> {
+        self.world.characters()
+    }
+    fn characters_mut(&mut self) -> &mut HashMap<String, Box<dyn Character>> {
+        self.world.characters_mut()
+    }
+    fn items(&self) -> &HashMap<String, Box<dyn Item>> {
+        self.world.items()
+    }
+    fn items_mut(&mut self) -> &mut HashMap<String, Box<dyn Item>> {
+        self.world.items_mut()
+    }
+    fn setup(&mut self, new_id: bool) {
+        self.world.setup(new_id)
+    }
+    fn finished(&self) -> bool {
+        self.world.finished()
+    }
+    fn event_count(&self) -> usize {
+        self.world.event_count()
+    }
+    fn event_inc(&mut self) {
+        self.world.event_inc()
+    }
+    fn id(&self) -> &Uuid {
+        self.world.id()
+    }
+    fn set_id(&mut self, id: Uuid) {
+        self.world.set_id(id)
+    }
+    fn version(&self) -> usize {
+        self.world.version()
+    }
+    fn get_message(&self, msgid: &str, args: Option<FluentArgs>) -> String {
+        let names: Vec<String> = args
+            .as_ref()
+            .map(|e| e.iter().map(|(k, _)| k.to_string()).collect())
+            .unwrap_or_default();
+        let res = self.world.get_message(msgid, args);
+        let mut requests = self.requests.borrow_mut();
+        let request = requests.entry(msgid.to_string()).or_default();
+        request.missing = res == msgid;
+        request.args.extend(names);
+        res
+    }
+}
+
+/// Requests texts of everything in the current state of the world
+fn request_texts(world: &RecordingWorld, narrator: &dyn Narrator) {
+    let description = world.description();
+    description.short(world);
+    description.long(world);
+    for scene in world.scenes().values() {
+        scene.short(world);
+        scene.long(world);
+    }
+    for character in world.characters().values() {
+        character.short(world);
+        character.long(world);
+    }
+    for item in world.items().values() {
+        item.short(world);
+        item.long(world);
+    }
+    for event in narrator.all_events(world) {
+        event.action_text(world);
+        event.success_text(world);
+        event.fail_text(world);
+    }
+}
+
+/// Collects messages which the world requests in the explored states in every language
+pub fn requested_messages(
+    world: &mut dyn World,
+    narrator: &dyn Narrator,
+    options: &ExploreOptions,
+) -> Result<(Requests, bool)> {
+    let graph = explore::explore(world, narrator, options)?;
+    let mut res = Requests::new();
+    for lang in world.available_languages() {
+        if !world.set_lang(&lang) {
+            return Err(anyhow!("Unsupported language '{}'", lang));
+        }
+        let recording = RecordingWorld {
+            world: &mut *world,
+            requests: RefCell::new(BTreeMap::new()),
+        };
+        for state in &graph.states {
+            recording.world.reset();
+            recording.world.load(state.world.clone())?;
+            request_texts(&recording, narrator);
+        }
+        res.insert(lang, recording.requests.into_inner());
+    }
+    Ok((res, graph.truncated))
+}
+
+/// Compares requested messages with the translated ones
+///
+/// only missing messages are reported for languages without `defined` messages
+/// and unused arguments are reported only when `unused_arguments` is set
+pub fn lint(
+    requests: &Requests,
+    defined: &HashMap<String, Messages>,
+    unused_arguments: bool,
+) -> Vec<Issue> {
+    let mut issues = vec![];
+    for (lang, requested) in requests {
+        let messages = defined.get(lang);
+        for (id, request) in requested {
+            if request.missing {
+                issues.push(Issue::Missing {
+                    lang: lang.clone(),
+                    id: id.clone(),
+                });
+            } else if let Some(used) = messages.and_then(|e| e.variables.get(id)) {
+                let missing: BTreeSet<String> = used.difference(&request.args).cloned().collect();
+                if !missing.is_empty() {
+                    issues.push(Issue::MissingArguments {
+                        lang: lang.clone(),
+                        id: id.clone(),
+                        missing,
+                    });
+                }
+                let unused: BTreeSet<String> = request.args.difference(used).cloned().collect();
+                if unused_arguments && !unused.is_empty() {
+                    issues.push(Issue::UnusedArguments {
+                        lang: lang.clone(),
+                        id: id.clone(),
+                        unused,
+                    });
+                }
+            }
+        }
+        if let Some(messages) = messages {
+            let mut unused: Vec<&String> = messages
+                .variables
+                .keys()
+                .filter(|e| !requested.contains_key(*e) && !messages.references.contains(*e))
+                .collect();
+            unused.sort();
+            issues.extend(unused.into_iter().map(|id| Issue::Unused {
+                lang: lang.clone(),
+                id: id.clone(),
+            }));
+        }
+    }
+    issues
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::{lint, Issue, Messages, Request, Requests};
+    use std::collections::{BTreeSet, HashMap};
+
+    #[test]
+    fn lint_messages() {
+        let mut messages = Messages::default();
+        messages
+            .parse(
+                "world-kitie-short = Kitie\n\
+                 world-pick-success = { $character } picked { $item }\n\
+                 world-unused = Unused { world-kitie-short }\n",
+            )
+            .unwrap();
+        assert_eq!(
+            messages.variables["world-pick-success"],
+            BTreeSet::from(["character".to_string(), "item".to_string()])
+        );
+
+        let mut requests = Requests::new();
+        requests.insert(
+            "en-US".to_string(),
+            [
+                (
+                    "world-pick-success".to_string(),
+                    Request {
+                        missing: false,
+                        args: BTreeSet::from(["character".to_string()]),
+                    },
+                ),
+                (
+                    "world-kitie-short".to_string(),
+                    Request {
+                        missing: false,
+                        args: BTreeSet::from(["count".to_string()]),
+                    },
+                ),
+                (
+                    "world-kitie-long".to_string(),
+                    Request {
+                        missing: true,
+                        args: BTreeSet::new(),
+                    },
+                ),
+            ]
+            .into_iter()
+            .collect(),
+        );
+        let defined = HashMap::from([("en-US".to_string(), messages)]);
+        let missing = Issue::Missing {
+            lang: "en-US".to_string(),
+            id: "world-kitie-long".to_string(),
+        };
+        let missing_arguments = Issue::MissingArguments {
+            lang: "en-US".to_string(),
+            id: "world-pick-success".to_string(),
+            missing: BTreeSet::from(["item".to_string()]),
+        };
+        let unused = Issue::Unused {
+            lang: "en-US".to_string(),
+            id: "world-unused".to_string(),
+        };
+        assert_eq!(
+            lint(&requests, &defined, false),
+            vec![missing.clone(), missing_arguments.clone(), unused.clone()]
+        );
+        assert_eq!(
+            lint(&requests, &defined, true),
+            vec![
+                missing,
+                Issue::UnusedArguments {
+                    lang: "en-US".to_string(),
+                    id: "world-kitie-short".to_string(),
+                    unused: BTreeSet::from(["count".to_string()]),
+                },
+                missing_arguments,
+                unused,
+            ]
+        );
+    }
+}
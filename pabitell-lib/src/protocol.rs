@@ -19,6 +19,17 @@ pub enum NotificationMessage {
     Joined(JoinedNotification),
     /// World was updated
     WorldUpdate,
+    /// Device was elected as the new owner of the world
+    /// (it becomes the owner after it claims the ownership)
+    Elected(ElectedNotification),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ElectedNotification {
+    pub world_id: Uuid,
+    /// Join secrets registered on the server
+    #[serde(default)]
+    pub secrets: Option<JoinSecrets>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -38,6 +49,30 @@ pub enum RequestMessage {
     /// World obtained
     GetWorld(GetWorldRequest),
     TriggerEvent(TriggerEventRequest),
+    /// Register sender as the owner of the world (handled by the server)
+    ClaimOwnership(ClaimOwnershipRequest),
+    /// Hand the world over to other connected device
+    TransferOwnership(TransferOwnershipRequest),
+}
+
+impl RequestMessage {
+    pub fn msg_id(&self) -> Uuid {
+        match self {
+            Self::GetWorld(request) => request.msg_id,
+            Self::TriggerEvent(request) => request.msg_id,
+            Self::ClaimOwnership(request) => request.msg_id,
+            Self::TransferOwnership(request) => request.msg_id,
+        }
+    }
+
+    pub fn world_id(&self) -> Uuid {
+        match self {
+            Self::GetWorld(request) => request.world_id,
+            Self::TriggerEvent(request) => request.world_id,
+            Self::ClaimOwnership(request) => request.world_id,
+            Self::TransferOwnership(request) => request.world_id,
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -53,11 +88,54 @@ pub struct TriggerEventRequest {
     pub world_id: Uuid,
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ClaimOwnershipRequest {
+    pub msg_id: Uuid,
+    pub world_id: Uuid,
+    /// Secrets which the server should require from joining devices
+    #[serde(default)]
+    pub secrets: Option<JoinSecrets>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct EventRecord {
+    pub idx: u64,
+    pub event: Value,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TransferOwnershipRequest {
+    pub msg_id: Uuid,
+    pub world_id: Uuid,
+    pub world: Value,
+    pub version: usize,
+    pub name: Option<String>,
+    pub events: Vec<EventRecord>,
+    #[serde(default)]
+    pub secrets: Option<JoinSecrets>,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(tag = "response", rename_all = "snake_case")]
 pub enum ResponseMessage {
     GetWorld(GetWorldResponse),
     TriggerEvent(TriggerEventResponse),
+    ClaimOwnership(ClaimOwnershipResponse),
+    TransferOwnership(TransferOwnershipResponse),
+    /// Request failed
+    Error(ErrorResponse),
+}
+
+impl ResponseMessage {
+    pub fn msg_id(&self) -> Option<Uuid> {
+        match self {
+            Self::GetWorld(response) => Some(response.msg_id),
+            Self::TriggerEvent(response) => Some(response.msg_id),
+            Self::ClaimOwnership(response) => Some(response.msg_id),
+            Self::TransferOwnership(response) => Some(response.msg_id),
+            Self::Error(response) => response.msg_id,
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -71,3 +149,199 @@ pub struct TriggerEventResponse {
     pub msg_id: Uuid,
     pub success: bool,
 }
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ClaimOwnershipResponse {
+    pub msg_id: Uuid,
+    /// Other device already owns the world when `false`
+    pub granted: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TransferOwnershipResponse {
+    pub msg_id: Uuid,
+    pub accepted: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    /// World is not known to the responder
+    UnknownWorld,
+    /// Event can't be triggered in the current state of the world
+    EventNotAvailable,
+    /// Message or its content can't be parsed
+    ParseError,
+    /// Stored world has a different format version
+    VersionMismatch,
+    /// Responder doesn't own the world
+    NotOwner,
+    /// No other device is connected to take over the world
+    NoCandidate,
+    /// Role of the device doesn't allow the request
+    AccessDenied,
+    /// Message was refused by the server (e.g. it targets a different world)
+    InvalidMessage,
+    /// Nobody responded to the request in time
+    Timeout,
+}
+
+impl ErrorCode {
+    /// Id of the translation which describes the error
+    pub fn msgid(&self) -> &'static str {
+        match self {
+            Self::UnknownWorld => "error_unknown_world",
+            Self::EventNotAvailable => "error_event_not_available",
+            Self::ParseError => "error_parse_error",
+            Self::VersionMismatch => "error_version_mismatch",
+            Self::NotOwner => "error_not_owner",
+            Self::NoCandidate => "error_no_candidate",
+            Self::AccessDenied => "error_access_denied",
+            Self::InvalidMessage => "error_invalid_message",
+            Self::Timeout => "error_timeout",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ErrorResponse {
+    /// Id of the failed request (`None` when it can't be determined)
+    pub msg_id: Option<Uuid>,
+    pub code: ErrorCode,
+    pub detail: Option<String>,
+}
+
+impl ErrorResponse {
+    pub fn new(msg_id: Option<Uuid>, code: ErrorCode, detail: Option<String>) -> Self {
+        Self {
+            msg_id,
+            code,
+            detail,
+        }
+    }
+}
+
+/// Role of a device connected to a world
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    /// Can see and control all characters
+    Narrator,
+    /// Plays a single character
+    Character,
+    /// Can only watch the story
+    Spectator,
+}
+
+/// Secrets required to join a world in a certain role
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct JoinSecrets {
+    pub narrator: String,
+    pub character: String,
+    pub spectator: String,
+}
+
+impl JoinSecrets {
+    /// Generates new random secrets
+    pub fn generate() -> Self {
+        Self {
+            narrator: Uuid::new_v4().simple().to_string(),
+            character: Uuid::new_v4().simple().to_string(),
+            spectator: Uuid::new_v4().simple().to_string(),
+        }
+    }
+
+    pub fn secret(&self, role: Role) -> &str {
+        match role {
+            Role::Narrator => &self.narrator,
+            Role::Character => &self.character,
+            Role::Spectator => &self.spectator,
+        }
+    }
+
+    /// Role which is granted by the secret
+    pub fn role(&self, secret: &str) -> Option<Role> {
+        [Role::Narrator, Role::Character, Role::Spectator]
+            .into_iter()
+            .find(|role| self.secret(*role) == secret)
+    }
+}
+
+/// Websocket subprotocol which is selected by the server
+pub const WS_PROTOCOL: &str = "pabitell";
+/// Join secret is passed as a websocket subprotocol (`pabitell.secret.<secret>`),
+/// because browsers can't set other headers and urls end up in access logs
+const WS_SECRET_PREFIX: &str = "pabitell.secret.";
+/// Header with the join secret used by the REST API
+pub const SECRET_HEADER: &str = "x-pabitell-secret";
+
+/// Subprotocols offered by a device which joins a world
+pub fn ws_protocols(secret: Option<&str>) -> Vec<String> {
+    let mut res = vec![WS_PROTOCOL.to_string()];
+    if let Some(secret) = secret {
+        res.push(format!("{}{}", WS_SECRET_PREFIX, secret));
+    }
+    res
+}
+
+/// Join secret from the value of `Sec-WebSocket-Protocol` header
+pub fn ws_secret(protocols: &str) -> Option<String> {
+    protocols
+        .split(',')
+        .find_map(|e| e.trim().strip_prefix(WS_SECRET_PREFIX))
+        .map(String::from)
+}
+
+impl From<ErrorResponse> for Message {
+    fn from(error: ErrorResponse) -> Self {
+        Self::Response(ResponseMessage::Error(error))
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::{
+        ws_protocols, ws_secret, ErrorCode, ErrorResponse, JoinSecrets, Message, ResponseMessage,
+        Role,
+    };
+
+    #[test]
+    fn error_response() {
+        let message: Message = ErrorResponse::new(None, ErrorCode::ParseError, None).into();
+        let json = serde_json::to_value(&message).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "msg": "response",
+                "response": "error",
+                "msg_id": null,
+                "code": "parse_error",
+                "detail": null,
+            })
+        );
+
+        match serde_json::from_value(json).unwrap() {
+            Message::Response(ResponseMessage::Error(error)) => {
+                assert_eq!(error.code, ErrorCode::ParseError)
+            }
+            _ => panic!("error response expected"),
+        }
+    }
+
+    #[test]
+    fn join_secrets() {
+        let secrets = JoinSecrets::generate();
+        assert_ne!(secrets.narrator, secrets.character);
+        assert_ne!(secrets.character, secrets.spectator);
+
+        for role in [Role::Narrator, Role::Character, Role::Spectator] {
+            assert_eq!(secrets.role(secrets.secret(role)), Some(role));
+        }
+        assert_eq!(secrets.role(""), None);
+        assert_eq!(secrets.role(&JoinSecrets::generate().narrator), None);
+
+        let header = ws_protocols(Some(&secrets.character)).join(", ");
+        assert_eq!(ws_secret(&header), Some(secrets.character));
+        assert_eq!(ws_secret(&ws_protocols(None).join(", ")), None);
+    }
+}
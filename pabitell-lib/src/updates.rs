@@ -1,9 +1,17 @@
-use crate::{ItemState, World};
+use crate::{
+    graph::{Link, Node},
+    ItemState, World,
+};
 use anyhow::{anyhow, Result};
 use std::fmt;
 
 pub trait Change: fmt::Debug {
     fn change(&self, world: &mut dyn World) -> Result<()>;
+
+    /// Scenes, characters and items which are affected by the change (see `graph`)
+    fn links(&self) -> Vec<Link> {
+        vec![]
+    }
 }
 
 pub struct AssignItemChange {
@@ -56,6 +64,16 @@ impl Change for AssignItemChange {
 
         Ok(())
     }
+
+    fn links(&self) -> Vec<Link> {
+        vec![match &self.state {
+            ItemState::Owned(character) => {
+                Link::new(Node::Character(character.clone()), &self.item)
+            }
+            ItemState::InScene(scene) => Link::new(Node::Scene(scene.clone()), &self.item),
+            ItemState::Unassigned => Link::new(Node::Item(self.item.clone()), "unassigned"),
+        }]
+    }
 }
 
 pub struct MoveCharacterChange {
@@ -96,6 +114,14 @@ impl Change for MoveCharacterChange {
             .set_scene(self.scene.clone());
         Ok(())
     }
+
+    fn links(&self) -> Vec<Link> {
+        vec![if let Some(scene) = self.scene.as_ref() {
+            Link::new(Node::Scene(scene.clone()), &self.character)
+        } else {
+            Link::new(Node::Character(self.character.clone()), "leaves")
+        }]
+    }
 }
 
 pub struct NextSceneDialogChange {
@@ -125,4 +151,8 @@ impl Change for NextSceneDialogChange {
             .next_dialog();
         Ok(())
     }
+
+    fn links(&self) -> Vec<Link> {
+        vec![Link::new(Node::Scene(self.scene.clone()), "next dialog")]
+    }
 }
@@ -10,6 +10,9 @@ use crate::translations::get_message_global;
 pub struct Props {
     pub character: Rc<characters::Character>,
     pub world_id: Uuid,
+    pub secret: Option<String>,
+    /// Join as a spectator instead of the character
+    pub spectator: bool,
     pub show_qr_cb: Callback<Rc<Vec<u8>>>,
     pub lang: String,
 }
@@ -31,7 +34,18 @@ impl Component for ActionJoin {
 
                 let character = ctx.props().character.clone();
                 let character_code: Option<String> = character.code.as_ref().clone();
-                let data = characters::CharacterQRJson::new(character_code, ctx.props().world_id);
+                let data = if ctx.props().spectator {
+                    characters::CharacterQRJson::spectator(
+                        ctx.props().world_id,
+                        ctx.props().secret.clone(),
+                    )
+                } else {
+                    characters::CharacterQRJson::new(
+                        character_code,
+                        ctx.props().world_id,
+                        ctx.props().secret.clone(),
+                    )
+                };
                 let data = Rc::new(serde_json::to_vec(&data).unwrap());
                 ctx.props().show_qr_cb.emit(data);
             }
@@ -53,7 +67,17 @@ impl Component for ActionJoin {
             FluentValue::from(character.short.to_string()),
         );
 
-        let help_text = get_message_global("will_join_the_game", &ctx.props().lang, Some(f_args));
+        let (title, help_text) = if ctx.props().spectator {
+            (
+                get_message_global("spectator", &ctx.props().lang, None),
+                get_message_global("will_spectate_the_game", &ctx.props().lang, None),
+            )
+        } else {
+            (
+                character.short.to_string(),
+                get_message_global("will_join_the_game", &ctx.props().lang, Some(f_args)),
+            )
+        };
 
         html! {
             <div class="column card is-12-mobile is-6-tablet is-3-desktop is-3-widescreen is-3-fullhd">
@@ -65,7 +89,7 @@ impl Component for ActionJoin {
                             </figure>
                         </div>
                         <div class="media-content">
-                            <p class="title is-4">{character.short.clone()}</p>
+                            <p class="title is-4">{title}</p>
                             <p class="subtitle is-6"></p>
                         </div>
                     </div>
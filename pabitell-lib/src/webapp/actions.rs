@@ -12,7 +12,7 @@ use super::{
     qrcode::{Msg as QRCodeMsg, QRCode},
     qrscanner::{Msg as QRScannerMsg, QRScanner},
 };
-use crate::translations::get_message_global;
+use crate::{protocol, translations::get_message_global};
 
 #[derive(Clone, Debug, Default, Properties)]
 pub struct Props {
@@ -23,6 +23,10 @@ pub struct Props {
     pub events: Vec<Rc<action_event::ActionEventItem>>,
     pub trigger_event_data: Callback<Value>,
     pub world_id: Uuid,
+    /// Join secrets of the owned world
+    pub secrets: Option<protocol::JoinSecrets>,
+    /// Secret which was used to join the world
+    pub secret: Option<String>,
     pub actions_scope: Rc<RefCell<Option<html::Scope<Actions>>>>,
     pub finished: bool,
     pub nav_data: Vec<NavigationData>,
@@ -38,6 +42,8 @@ impl PartialEq for Props {
             && self.events == other.events
             && self.trigger_event_data == other.trigger_event_data
             && self.world_id == other.world_id
+            && self.secrets == other.secrets
+            && self.secret == other.secret
             && self.finished == other.finished
             && self.nav_data == other.nav_data
     }
@@ -238,7 +244,21 @@ impl Component for Actions {
         };
 
         let joinable_characters: Vec<_> = if props.character.is_none() && !props.finished {
-            ctx.props().available_characters.iter().collect()
+            ctx.props()
+                .available_characters
+                .iter()
+                .map(|e| (e, false))
+                .collect()
+        } else {
+            vec![]
+        };
+        // Only the owner knows the secret of spectators
+        let spectator_join: Vec<_> = if props.secrets.is_some() {
+            joinable_characters
+                .iter()
+                .filter(|(e, _)| e.code.is_none())
+                .map(|(e, _)| (*e, true))
+                .collect()
         } else {
             vec![]
         };
@@ -272,14 +292,29 @@ impl Component for Actions {
         };
 
         let link = ctx.link().clone();
-        let render_join = |character: &Rc<characters::Character>| {
+        let render_join = |(character, spectator): (&Rc<characters::Character>, bool)| {
             let world_id = props.world_id;
             let show_qr_cb = link.callback(Msg::QRCodeShow);
+            // Devices which don't own the world can only share their own secret
+            let secret = if let Some(secrets) = props.secrets.as_ref() {
+                let role = if spectator {
+                    protocol::Role::Spectator
+                } else if character.code.is_some() {
+                    protocol::Role::Character
+                } else {
+                    protocol::Role::Narrator
+                };
+                Some(secrets.secret(role).to_string())
+            } else {
+                props.secret.clone()
+            };
             html! {
                 <action_join::ActionJoin
                   {show_qr_cb}
                   character={character.clone()}
                   {world_id}
+                  {secret}
+                  {spectator}
                   lang={ctx.props().lang.clone()}
                 />
             }
@@ -311,7 +346,8 @@ impl Component for Actions {
                 <div class="columns is-flex-wrap-wrap w-100">
                     { for nav_data.into_iter().map(move |e| geo_navs(e)) }
                     { for characters.clone().into_iter().map(|e| qr_scans(e.clone())) }
-                    { for joinable_characters.iter().map(|e| render_join(e)) }
+                    { for joinable_characters.into_iter().map(render_join) }
+                    { for spectator_join.into_iter().map(render_join) }
                     { for items.iter().map(render_item) }
                     <QRScanner
                       qr_found={qr_found_cb}
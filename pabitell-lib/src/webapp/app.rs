@@ -47,7 +47,7 @@ const WS_TIMEOUT: u32 = 3000; // ws timeout in ms
 pub enum Msg {
     UpdateCharacter(Rc<Option<String>>),
     TriggerEventData(Value),
-    TriggerRestoreCharacter(Option<String>, bool, Uuid),
+    TriggerRestoreCharacter(characters::CharacterQRJson, bool),
     PlayText(String),
     Leave,
     Reset,
@@ -72,6 +72,10 @@ pub enum Msg {
     UpdateSceneLocation(String, Option<Point>),
     ShowEditor(bool),
     SetWorldName(Option<String>),
+    HandOver,
+    WsClaimOwnership,
+    SetOwned(bool),
+    SetSecrets(Option<protocol::JoinSecrets>),
 }
 
 pub struct App {
@@ -86,6 +90,12 @@ pub struct App {
     character: Rc<Option<String>>,
     /// If it is sent can't switch to other characters
     fixed_character: bool,
+    /// Secret used to join the world
+    secret: Option<String>,
+    /// All join secrets of the owned world
+    secrets: Option<protocol::JoinSecrets>,
+    /// World is only watched (e.g. on a projector)
+    spectator: bool,
     messages_scope: Rc<RefCell<Option<html::Scope<Messages>>>>,
     speech_scope: Rc<RefCell<Option<html::Scope<Speech>>>>,
     client_scope: Rc<RefCell<Option<html::Scope<WebsocketClient>>>>,
@@ -168,6 +178,12 @@ impl Component for App {
                 .unwrap_or_else(|_| "false".to_string())
                 .parse()
                 .unwrap_or(false),
+            secret: storage::LocalStorage::get("secret").ok(),
+            secrets: None,
+            spectator: storage::LocalStorage::get("spectator")
+                .unwrap_or_else(|_| "false".to_string())
+                .parse()
+                .unwrap_or(false),
             messages_scope: Rc::new(RefCell::new(None)),
             speech_scope: Rc::new(RefCell::new(None)),
             client_scope: Rc::new(RefCell::new(None)),
@@ -222,6 +238,10 @@ impl Component for App {
                 }
             }
             Msg::TriggerEventData(json_value) => {
+                if self.spectator {
+                    log::warn!("Spectators can't trigger events");
+                    return false;
+                }
                 let value = json_value.clone();
                 let narrator = ctx.props().make_narrator.as_ref().unwrap()();
                 if let Some(world) = self.world.as_mut() {
@@ -251,8 +271,8 @@ impl Component for App {
                         self.request_to_trigger_event(ctx, self.world_id.unwrap(), event.dump());
                     } else {
                         // Can't construct event based on given data
-                        // TODO some error message
                         log::warn!("Failed to parse event from {}", value);
+                        self.show_error(protocol::ErrorCode::ParseError);
                         return false;
                     }
                 } else {
@@ -260,11 +280,31 @@ impl Component for App {
                 }
                 false
             }
-            Msg::TriggerRestoreCharacter(character, fixed_character, world_id) => {
+            Msg::TriggerRestoreCharacter(character_json, fixed_character) => {
+                let characters::CharacterQRJson {
+                    character,
+                    world_id,
+                    secret,
+                    spectator,
+                } = character_json;
+
                 // check whether character exists in the world
                 let world = ctx.props().make_world.as_ref().unwrap()(&self.lang);
                 self.load_failed = None;
 
+                // update spectator mode
+                storage::LocalStorage::set("spectator", if spectator { "true" } else { "false" })
+                    .unwrap();
+                self.spectator = spectator;
+
+                // update secret
+                if let Some(secret) = secret.as_ref() {
+                    storage::LocalStorage::set("secret", secret).unwrap();
+                } else {
+                    storage::LocalStorage::delete("secret");
+                }
+                self.secret = secret;
+
                 // update charactes
                 storage::LocalStorage::set(
                     "fixed_character",
@@ -339,6 +379,8 @@ impl Component for App {
                 storage::LocalStorage::delete("world_id");
                 storage::LocalStorage::delete("fixed_character");
                 storage::LocalStorage::delete("character");
+                storage::LocalStorage::delete("secret");
+                storage::LocalStorage::delete("spectator");
                 if let Some(scope) = self.messages_scope.as_ref().borrow().clone() {
                     scope.send_message(MessagesMsg::Clear);
                 }
@@ -349,6 +391,10 @@ impl Component for App {
                 self.character = Rc::new(None);
                 // clear owned flag
                 self.owned = None;
+                // clear secrets
+                self.secret = None;
+                self.secrets = None;
+                self.spectator = false;
 
                 // clear loding state
                 *self.loading.borrow_mut() = false;
@@ -384,6 +430,9 @@ impl Component for App {
                                     version: world.version(),
                                     owned: true,
                                     name: world_name,
+                                    secret: None,
+                                    secrets: None,
+                                    spectator: false,
                                 },
                             )
                             .await
@@ -397,6 +446,10 @@ impl Component for App {
                 false
             }
             Msg::WorldUpdateFetched(world, owned) => {
+                if owned && self.owned != Some(true) {
+                    // Let the server know which device owns the world
+                    ctx.link().send_message(Msg::WsClaimOwnership);
+                }
                 self.owned = Some(owned);
                 let old_screen_text = App::screen_text(&self.world, &self.character);
                 self.event_count = world.event_count();
@@ -419,6 +472,8 @@ impl Component for App {
                 let mut world = ctx.props().make_world.as_ref().unwrap()(&self.lang);
                 self.load_failed = None;
                 world.setup(true);
+                let secrets = protocol::JoinSecrets::generate();
+                self.set_secrets(Some(secrets.clone()));
                 let name = ctx.props().name.clone();
                 let link = ctx.link().clone();
                 spawn_local(async move {
@@ -434,6 +489,9 @@ impl Component for App {
                             version: world.version(),
                             owned: true,
                             name: None,
+                            secret: Some(secrets.narrator.clone()),
+                            secrets: Some(secrets),
+                            spectator: false,
                         },
                     )
                     .await
@@ -544,30 +602,194 @@ impl Component for App {
                                     }
                                 }
                             }
+                            protocol::NotificationMessage::Elected(elected) => {
+                                // Owner left, promote the local copy of the world
+                                let world = if let Some(world) = self.world.as_ref() {
+                                    world
+                                } else {
+                                    return false;
+                                };
+                                if world.id() != &elected.world_id
+                                    || self.owned == Some(true)
+                                    || self.spectator
+                                {
+                                    return false;
+                                }
+                                log::info!("Elected as the owner of world {}", elected.world_id);
+
+                                let mut new_world =
+                                    ctx.props().make_world.as_ref().unwrap()(&self.lang);
+                                new_world.load(world.dump()).unwrap();
+                                new_world.set_id(elected.world_id);
+                                self.show_notice("ownership_elected");
+                                self.set_secrets(elected.secrets.clone());
+
+                                let name = ctx.props().name.clone();
+                                let world_name = self.world_name.clone();
+                                let character: Option<String> = self.character.as_ref().clone();
+                                let fixed_character = self.fixed_character;
+                                let link = ctx.link().clone();
+                                spawn_local(async move {
+                                    let db = database::init_database(&name).await;
+                                    database::put_world(
+                                        &db,
+                                        database::StoredWorld {
+                                            id: elected.world_id,
+                                            character,
+                                            last: Utc::now(),
+                                            fixed_character,
+                                            data: new_world.dump(),
+                                            version: new_world.version(),
+                                            owned: true,
+                                            name: world_name,
+                                            secret: None,
+                                            secrets: elected.secrets,
+                                            spectator: false,
+                                        },
+                                    )
+                                    .await
+                                    .unwrap();
+                                    database::set_owned(&db, &elected.world_id, true)
+                                        .await
+                                        .unwrap();
+                                    link.send_message(Msg::WorldUpdateFetched(new_world, true));
+                                    // Server waits for the claim before it makes us the owner
+                                    link.send_message(Msg::WsClaimOwnership);
+                                    // Other clients might have a newer world than the elected one
+                                    link.send_message(Msg::WsNotifyUpdateWorld);
+                                });
+                            }
                         }
                     }
+                    Ok(protocol::Message::Request(
+                        protocol::RequestMessage::TransferOwnership(transfer),
+                    )) => {
+                        // Server sends the request only to the selected candidate
+                        let mut world = ctx.props().make_world.as_ref().unwrap()(&self.lang);
+                        let accepted = Some(transfer.world_id) == self.world_id
+                            && !self.spectator
+                            && transfer.version == world.version()
+                            && world.load(transfer.world.clone()).is_ok();
+                        world.set_id(transfer.world_id);
+
+                        let name = ctx.props().name.clone();
+                        let client_scope = self.client_scope.clone();
+                        let character: Option<String> = self.character.as_ref().clone();
+                        let fixed_character = self.fixed_character;
+                        if accepted {
+                            self.show_notice("ownership_elected");
+                            self.set_secrets(transfer.secrets.clone());
+                        }
+                        ctx.link().send_future(async move {
+                            let protocol::TransferOwnershipRequest {
+                                msg_id,
+                                world_id,
+                                name: world_name,
+                                events,
+                                secrets,
+                                ..
+                            } = transfer;
+                            if accepted {
+                                let db = database::init_database(&name).await;
+                                database::put_world(
+                                    &db,
+                                    database::StoredWorld {
+                                        id: world_id,
+                                        character,
+                                        last: Utc::now(),
+                                        fixed_character,
+                                        data: world.dump(),
+                                        version: world.version(),
+                                        owned: true,
+                                        name: world_name,
+                                        secret: None,
+                                        secrets,
+                                        spectator: false,
+                                    },
+                                )
+                                .await
+                                .unwrap();
+                                database::set_owned(&db, &world_id, true).await.unwrap();
+                                for record in events {
+                                    database::put_event(&db, &world_id, record.idx, record.event)
+                                        .await
+                                        .unwrap();
+                                }
+                            }
+
+                            if let Some(client_scope) = client_scope.as_ref().borrow().as_ref() {
+                                let resp = protocol::Message::Response(
+                                    protocol::ResponseMessage::TransferOwnership(
+                                        protocol::TransferOwnershipResponse { msg_id, accepted },
+                                    ),
+                                );
+                                client_scope.send_message(WsMsg::SendMessage(
+                                    serde_json::to_string(&resp).unwrap(),
+                                ));
+                            }
+
+                            if accepted {
+                                Msg::WorldUpdateFetched(world, true)
+                            } else {
+                                Msg::WsFlush
+                            }
+                        });
+                    }
                     Ok(protocol::Message::Request(request)) => {
                         if self.owned == Some(true) {
                             let name = ctx.props().name.clone();
                             let client_scope = self.client_scope.clone();
                             match request {
                                 protocol::RequestMessage::GetWorld(get_world) => {
+                                    let version =
+                                        ctx.props().make_world.as_ref().unwrap()(&self.lang)
+                                            .version();
                                     ctx.link().send_future(async move {
                                         let protocol::GetWorldRequest { msg_id, world_id } =
                                             get_world;
 
                                         let db = database::init_database(&name).await;
 
-                                        let world = database::get_world(&db, &world_id)
-                                            .await
-                                            .unwrap()
-                                            .map(|record| record.data.clone());
-
-                                        let resp = protocol::Message::Response(
-                                            protocol::ResponseMessage::GetWorld(
-                                                protocol::GetWorldResponse { msg_id, world },
+                                        let resp: protocol::Message = match database::get_world(
+                                            &db, &world_id,
+                                        )
+                                        .await
+                                        .unwrap()
+                                        {
+                                            Some(record) if !record.owned => {
+                                                protocol::ErrorResponse::new(
+                                                    Some(msg_id),
+                                                    protocol::ErrorCode::NotOwner,
+                                                    None,
+                                                )
+                                                .into()
+                                            }
+                                            Some(record) if record.version != version => {
+                                                protocol::ErrorResponse::new(
+                                                    Some(msg_id),
+                                                    protocol::ErrorCode::VersionMismatch,
+                                                    Some(format!(
+                                                        "{} != {}",
+                                                        record.version, version
+                                                    )),
+                                                )
+                                                .into()
+                                            }
+                                            Some(record) => protocol::Message::Response(
+                                                protocol::ResponseMessage::GetWorld(
+                                                    protocol::GetWorldResponse {
+                                                        msg_id,
+                                                        world: Some(record.data),
+                                                    },
+                                                ),
                                             ),
-                                        );
+                                            None => protocol::ErrorResponse::new(
+                                                Some(msg_id),
+                                                protocol::ErrorCode::UnknownWorld,
+                                                None,
+                                            )
+                                            .into(),
+                                        };
                                         if let Some(client_scope) =
                                             client_scope.as_ref().borrow().as_ref()
                                         {
@@ -591,85 +813,103 @@ impl Component for App {
                                         } = trigger_event;
                                         let db = database::init_database(&name).await;
 
-                                        let success = if let Some(record) =
+                                        // First try to get world from database
+                                        let result = if let Some(record) =
                                             database::get_world(&db, &world_id.clone())
                                                 .await
                                                 .unwrap()
                                         {
-                                            // First try to get world from database
-                                            world.load(record.data.clone()).unwrap();
-                                            world.set_id(world_id);
-                                            if let Some(mut event) =
-                                                narrator.parse_event(world.as_ref(), event)
+                                            if !record.owned {
+                                                Err(protocol::ErrorCode::NotOwner)
+                                            } else if record.version != world.version()
+                                                || world.load(record.data.clone()).is_err()
                                             {
-                                                if event.can_be_triggered(world.as_ref()) {
-                                                    // Apply event
-                                                    // Send response
-                                                    event.trigger(world.as_mut());
-
-                                                    // Store world
-                                                    database::put_world(
-                                                        &db,
-                                                        database::StoredWorld {
-                                                            id: world.id().to_owned(),
-                                                            character: None,
-                                                            last: Utc::now(),
-                                                            fixed_character: false,
-                                                            data: world.dump(),
-                                                            version: world.version(),
-                                                            owned: true,
-                                                            name: record.name,
-                                                        },
-                                                    )
-                                                    .await
-                                                    .unwrap();
-
-                                                    // Send notification that event was triggered
-                                                    let notification =
-                                                        protocol::Message::Notification(
-                                                            protocol::NotificationMessage::Event(
-                                                                protocol::EventNotification {
-                                                                    event: event.dump(),
-                                                                    event_count: world
-                                                                        .event_count(),
-                                                                },
-                                                            ),
-                                                        );
-                                                    if let Some(client_scope) =
-                                                        client_scope.as_ref().borrow().as_ref()
+                                                Err(protocol::ErrorCode::VersionMismatch)
+                                            } else {
+                                                world.set_id(world_id);
+                                                match narrator.parse_event(world.as_ref(), event) {
+                                                    Some(mut event)
+                                                        if event.can_be_triggered(world.as_ref()) =>
                                                     {
-                                                        client_scope.send_message(
-                                                            WsMsg::SendMessage(
-                                                                serde_json::to_string(
-                                                                    &notification,
-                                                                )
-                                                                .unwrap(),
-                                                            ),
-                                                        );
+                                                        // Apply event
+                                                        // Send response
+                                                        event.trigger(world.as_mut());
+
+                                                        // Store world
+                                                        database::put_world(
+                                                            &db,
+                                                            database::StoredWorld {
+                                                                id: world.id().to_owned(),
+                                                                character: None,
+                                                                last: Utc::now(),
+                                                                fixed_character: false,
+                                                                data: world.dump(),
+                                                                version: world.version(),
+                                                                owned: true,
+                                                                name: record.name,
+                                                                secret: None,
+                                                                secrets: None,
+                                                                spectator: false,
+                                                            },
+                                                        )
+                                                        .await
+                                                        .unwrap();
+
+                                                        // Send notification that event was triggered
+                                                        let notification =
+                                                            protocol::Message::Notification(
+                                                                protocol::NotificationMessage::Event(
+                                                                    protocol::EventNotification {
+                                                                        event: event.dump(),
+                                                                        event_count: world
+                                                                            .event_count(),
+                                                                    },
+                                                                ),
+                                                            );
+                                                        if let Some(client_scope) =
+                                                            client_scope.as_ref().borrow().as_ref()
+                                                        {
+                                                            client_scope.send_message(
+                                                                WsMsg::SendMessage(
+                                                                    serde_json::to_string(
+                                                                        &notification,
+                                                                    )
+                                                                    .unwrap(),
+                                                                ),
+                                                            );
+                                                        }
+
+                                                        Ok(())
                                                     }
-
-                                                    true
-                                                } else {
-                                                    false
+                                                    Some(_) => {
+                                                        Err(protocol::ErrorCode::EventNotAvailable)
+                                                    }
+                                                    None => Err(protocol::ErrorCode::ParseError),
                                                 }
-                                            } else {
-                                                false
                                             }
                                         } else {
-                                            false
+                                            Err(protocol::ErrorCode::UnknownWorld)
                                         };
 
                                         if let Some(client_scope) =
                                             client_scope.as_ref().borrow().as_ref()
                                         {
-                                            let resp = protocol::Message::Response(
-                                                protocol::ResponseMessage::TriggerEvent(
-                                                    protocol::TriggerEventResponse {
-                                                        msg_id,
-                                                        success,
-                                                    },
+                                            let resp: protocol::Message = match result {
+                                                Ok(()) => protocol::Message::Response(
+                                                    protocol::ResponseMessage::TriggerEvent(
+                                                        protocol::TriggerEventResponse {
+                                                            msg_id,
+                                                            success: true,
+                                                        },
+                                                    ),
                                                 ),
-                                            );
+                                                Err(code) => protocol::ErrorResponse::new(
+                                                    Some(msg_id),
+                                                    code,
+                                                    None,
+                                                )
+                                                .into(),
+                                            };
 
                                             client_scope.send_message(WsMsg::SendMessage(
                                                 serde_json::to_string(&resp).unwrap(),
@@ -679,6 +919,9 @@ impl Component for App {
                                         Msg::WsFlush
                                     })
                                 }
+                                // handled by the server or by the candidate
+                                protocol::RequestMessage::ClaimOwnership(_)
+                                | protocol::RequestMessage::TransferOwnership(_) => {}
                             }
                         }
                     }
@@ -696,12 +939,18 @@ impl Component for App {
                                     let character: Option<String> = self.character.as_ref().clone();
                                     let name = ctx.props().name.clone();
                                     let world_name = self.world_name.clone();
+                                    let secret = self.secret.clone();
+                                    let spectator = self.spectator;
 
                                     if let Some(world_data) = get_world.world {
+                                        if let Err(err) = world.load(world_data) {
+                                            log::warn!("Failed to load world: {}", err);
+                                            self.show_error(protocol::ErrorCode::VersionMismatch);
+                                            return true;
+                                        }
                                         ctx.link().send_future(async move {
                                             let world_id = world_id.unwrap();
                                             world.set_id(world_id);
-                                            world.load(world_data).unwrap();
                                             log::debug!("World {} updated", world_id);
                                             let db = database::init_database(&name).await;
                                             database::put_world(
@@ -715,6 +964,9 @@ impl Component for App {
                                                     version: world.version(),
                                                     owned: false,
                                                     name: world_name,
+                                                    secret,
+                                                    secrets: None,
+                                                    spectator,
                                                 },
                                             )
                                             .await
@@ -744,6 +996,60 @@ impl Component for App {
                                 }
                             }
                         }
+                        protocol::ResponseMessage::ClaimOwnership(claim) => {
+                            // Server sends this response only to the claiming device
+                            if !claim.granted && self.owned == Some(true) {
+                                log::warn!("World is owned by other device");
+                                self.show_error(protocol::ErrorCode::NotOwner);
+                                ctx.link().send_message(Msg::SetOwned(false));
+                            }
+                        }
+                        protocol::ResponseMessage::TransferOwnership(transferred) => {
+                            if let Some((msg_id, timeout)) = self.request_id.take() {
+                                if transferred.msg_id == msg_id {
+                                    timeout.cancel();
+                                    *self.loading.borrow_mut() = false;
+                                    if transferred.accepted {
+                                        self.show_notice("ownership_transferred");
+                                        ctx.link().send_message(Msg::SetOwned(false));
+                                    }
+                                    return true;
+                                } else {
+                                    self.request_id = Some((msg_id, timeout));
+                                }
+                            }
+                        }
+                        protocol::ResponseMessage::Error(error) => {
+                            if let Some((msg_id, timeout)) = self.request_id.take() {
+                                if error.msg_id == Some(msg_id) {
+                                    timeout.cancel();
+                                    *self.loading.borrow_mut() = false;
+                                } else {
+                                    self.request_id = Some((msg_id, timeout));
+                                    if error.msg_id.is_some() {
+                                        // Response to a request of other client
+                                        return false;
+                                    }
+                                }
+                            } else if error.msg_id.is_some() {
+                                return false;
+                            }
+
+                            log::warn!("Request failed: {:?}", error);
+                            self.show_error(error.code);
+                            match error.code {
+                                // World on owner is lost
+                                protocol::ErrorCode::UnknownWorld => {
+                                    ctx.link().send_message(Msg::Leave)
+                                }
+                                // Local world is probably outdated
+                                protocol::ErrorCode::EventNotAvailable => {
+                                    ctx.link().send_message(Msg::RefreshWorld)
+                                }
+                                _ => {}
+                            }
+                            return true;
+                        }
                     },
                     Err(err) => {
                         log::warn!("Failed to parse WS data: {}", err);
@@ -756,8 +1062,23 @@ impl Component for App {
                 // Note that notification needs to be send once
                 // client_scope in initialized by the subcomponent
                 let client_scope = self.client_scope.clone();
+                let owned = self.owned == Some(true);
+                let link = ctx.link().clone();
 
                 self.ws_queue.drain(..).for_each(|msg| {
+                    // Server doesn't echo requests back so the owner needs to handle its own
+                    if owned
+                        && matches!(
+                            serde_json::from_str::<protocol::Message>(&msg),
+                            Ok(protocol::Message::Request(
+                                protocol::RequestMessage::GetWorld(_)
+                                    | protocol::RequestMessage::TriggerEvent(_)
+                            ))
+                        )
+                    {
+                        link.send_message(Msg::WsMessageRecieved(msg.clone()));
+                    }
+
                     let client_scope = client_scope.clone();
                     spawn_local(async move {
                         if let Some(client_scope) = client_scope.as_ref().borrow().as_ref() {
@@ -843,7 +1164,7 @@ impl Component for App {
             Msg::WsConnect(world_id) => {
                 let client_scope = self.client_scope.clone();
                 if let Some(client_scope) = client_scope.as_ref().borrow().as_ref() {
-                    client_scope.send_message(WsMsg::Connect(world_id));
+                    client_scope.send_message(WsMsg::Connect(world_id, self.secret.clone()));
                 }
                 true
             }
@@ -856,6 +1177,10 @@ impl Component for App {
                             .send_future(async move { Msg::WsGetWorld(world_id) });
                     }
                 }
+                if WsStatus::CONNECTED == status && self.owned == Some(true) {
+                    // Server forgets the owner when the connection is lost
+                    ctx.link().send_message(Msg::WsClaimOwnership);
+                }
                 self.ws_status = status;
                 true
             }
@@ -894,6 +1219,8 @@ impl Component for App {
                 self.load_failed = None;
                 let mut world = ctx.props().make_world.as_ref().unwrap()(&self.lang);
                 if world.load(world_json).is_ok() {
+                    let secrets = protocol::JoinSecrets::generate();
+                    self.set_secrets(Some(secrets.clone()));
                     let name = ctx.props().name.clone();
                     let link = ctx.link().clone();
                     spawn_local(async move {
@@ -909,6 +1236,9 @@ impl Component for App {
                                 version: world.version(),
                                 owned: true,
                                 name: None,
+                                secret: Some(secrets.narrator.clone()),
+                                secrets: Some(secrets),
+                                spectator: false,
                             },
                         )
                         .await
@@ -927,6 +1257,9 @@ impl Component for App {
                 true
             }
             Msg::PositionReached(character, point) => {
+                if self.spectator {
+                    return false;
+                }
                 if let Some(world) = self.world.as_ref() {
                     log::debug!("Character {} reached {:?}", character, point);
                     // same character and destination
@@ -981,6 +1314,9 @@ impl Component for App {
                                 version: world_version,
                                 owned: true,
                                 name: world_name,
+                                secret: None,
+                                secrets: None,
+                                spectator: false,
                             },
                         )
                         .await
@@ -1007,6 +1343,109 @@ impl Component for App {
                 self.world_name = name;
                 false
             }
+            Msg::HandOver => {
+                let world = if let Some(world) = self.world.as_ref() {
+                    world
+                } else {
+                    return false;
+                };
+                if self.owned != Some(true) {
+                    log::warn!("Trying to hand over not owned world");
+                    return false;
+                }
+
+                *self.loading.borrow_mut() = true;
+                let msg_id = Uuid::new_v4();
+                let link = ctx.link().clone();
+                self.request_id = Some((
+                    msg_id,
+                    Timeout::new(WS_TIMEOUT, move || link.send_message(Msg::WsFailed)),
+                ));
+
+                let name = ctx.props().name.clone();
+                let client_scope = self.client_scope.clone();
+                let world_id = world.id().to_owned();
+                let world_data = world.dump();
+                let version = world.version();
+                let world_name = self.world_name.clone();
+                let secrets = self.secrets.clone();
+                ctx.link().send_future(async move {
+                    // Send the event log along with the world
+                    let db = database::init_database(&name).await;
+                    let events = database::get_events(&db, &world_id)
+                        .await
+                        .unwrap()
+                        .into_iter()
+                        .filter_map(|record| {
+                            Some(protocol::EventRecord {
+                                idx: record["idx"].as_u64()?,
+                                event: record["data"].clone(),
+                            })
+                        })
+                        .collect();
+
+                    let request =
+                        protocol::Message::Request(protocol::RequestMessage::TransferOwnership(
+                            protocol::TransferOwnershipRequest {
+                                msg_id,
+                                world_id,
+                                world: world_data,
+                                version,
+                                name: world_name,
+                                events,
+                                secrets,
+                            },
+                        ));
+                    if let Some(client_scope) = client_scope.as_ref().borrow().as_ref() {
+                        client_scope.send_message(WsMsg::SendMessage(
+                            serde_json::to_string(&request).unwrap(),
+                        ));
+                    }
+                    Msg::WsFlush
+                });
+                true
+            }
+            Msg::WsClaimOwnership => {
+                if let Some(world_id) = self.world_id {
+                    self.ws_queue.push(
+                        serde_json::to_string(&protocol::Message::Request(
+                            protocol::RequestMessage::ClaimOwnership(
+                                protocol::ClaimOwnershipRequest {
+                                    msg_id: Uuid::new_v4(),
+                                    world_id,
+                                    secrets: self.secrets.clone(),
+                                },
+                            ),
+                        ))
+                        .unwrap(),
+                    );
+
+                    // Plan flushing of WS messages
+                    ctx.link().send_future(async { Msg::WsFlush });
+                }
+                false
+            }
+            Msg::SetSecrets(secrets) => {
+                self.set_secrets(secrets);
+                false
+            }
+            Msg::SetOwned(owned) => {
+                if let Some(world_id) = self.world_id {
+                    let name = ctx.props().name.clone();
+                    spawn_local(async move {
+                        let db = database::init_database(&name).await;
+                        database::set_owned(&db, &world_id, owned).await.unwrap();
+                    });
+                }
+                self.owned = Some(owned);
+                if !owned {
+                    self.show_editor = false;
+                    self.secrets = None;
+                    // World needs to be obtained from the new owner
+                    ctx.link().send_message(Msg::RefreshWorld);
+                }
+                true
+            }
         }
     }
 
@@ -1124,6 +1563,7 @@ impl Component for App {
             let leave_cb = link.callback(|_| Msg::Leave);
             let reset_cb = link.callback(|_| Msg::Reset);
             let edit_cb = link.callback(|_| Msg::ShowEditor(true));
+            let hand_over_cb = link.callback(|_| Msg::HandOver);
             let refresh_world_cb = link.callback(|_| Msg::RefreshWorld);
 
             let finished = if let Some(world) = self.world.as_ref() {
@@ -1144,6 +1584,51 @@ impl Component for App {
 
             let world_id = world.id().to_owned();
 
+            let main = if self.spectator {
+                html! {
+                    <main class="hero is-fullheight is-size-4">
+                        { self.view_spectator(world.as_ref()) }
+                        <Messages
+                          shared_scope={ self.messages_scope.clone() }
+                          lang={ Rc::new(self.lang.clone()) }
+                          world_name={ Rc::new(world.name().to_string()) }
+                        />
+                    </main>
+                }
+            } else {
+                html! {
+                    <main>
+                        <CharacterSwitch
+                          available_characters={ available_characters.clone() }
+                          set_character={ set_character_callback }
+                          character={ self.character.clone() }
+                          fixed={self.fixed_character}
+                        />
+                        <Actions
+                          {nav_data}
+                          lang={ self.lang.clone() }
+                          available_characters={ available_characters }
+                          { owned_items }
+                          character={ self.character.clone() }
+                          events={ events }
+                          trigger_event_data={ trigger_event_data_callback }
+                          { position_reached_cb }
+                          world_id={self.world_id.unwrap_or_default()}
+                          secrets={self.secrets.clone()}
+                          secret={self.secret.clone()}
+                          actions_scope={self.actions_scope.clone()}
+                          { finished }
+                        />
+                        { self.view_scene(ctx) }
+                        <Messages
+                          shared_scope={ self.messages_scope.clone() }
+                          lang={ Rc::new(self.lang.clone()) }
+                          world_name={ Rc::new(world.name().to_string()) }
+                        />
+                    </main>
+                }
+            };
+
             html! {
                 <>
                     <section class="hero is-small is-light">
@@ -1178,6 +1663,8 @@ impl Component for App {
                                         edit_world={edit_cb}
                                         can_reset={self.owned.unwrap_or(false)}
                                         can_edit={self.owned.unwrap_or(false)}
+                                        hand_over_world={hand_over_cb}
+                                        can_hand_over={self.owned.unwrap_or(false)}
                                         connect_ws={link.callback(move |_| Msg::WsConnect(world_id))}
                                         event_count={self.event_count}
                                         status={self.ws_status.clone()}
@@ -1188,33 +1675,7 @@ impl Component for App {
                           </div>
                       </div>
                     </section>
-                    <main>
-                        <CharacterSwitch
-                          available_characters={ available_characters.clone() }
-                          set_character={ set_character_callback }
-                          character={ self.character.clone() }
-                          fixed={self.fixed_character}
-                        />
-                        <Actions
-                          {nav_data}
-                          lang={ self.lang.clone() }
-                          available_characters={ available_characters }
-                          { owned_items }
-                          character={ self.character.clone() }
-                          events={ events }
-                          trigger_event_data={ trigger_event_data_callback }
-                          { position_reached_cb }
-                          world_id={self.world_id.unwrap_or_default()}
-                          actions_scope={self.actions_scope.clone()}
-                          { finished }
-                        />
-                        { self.view_scene(ctx) }
-                        <Messages
-                          shared_scope={ self.messages_scope.clone() }
-                          lang={ Rc::new(self.lang.clone()) }
-                          world_name={ Rc::new(world.name().to_string()) }
-                        />
-                    </main>
+                    { main }
                     <footer class="footer">
                         <div class="content has-text-centered">
                             <a href="https://github.com/shenek/pabitell/"> { "Pabitell" }</a>
@@ -1228,8 +1689,8 @@ impl Component for App {
             }
         } else {
             let new_world_cb = link.callback(|_| Msg::CreateNewWorld);
-            let character_scanned_cb = link.callback(|(character, fixed_character, world_id)| {
-                Msg::TriggerRestoreCharacter(character, fixed_character, world_id)
+            let character_scanned_cb = link.callback(|(character_json, fixed_character)| {
+                Msg::TriggerRestoreCharacter(character_json, fixed_character)
             });
             let show_print_cb = link.callback(Msg::ShowPrint);
 
@@ -1373,6 +1834,37 @@ impl App {
         }
     }
 
+    /// Scenes where the characters are shown to the spectators
+    fn view_spectator(&self, world: &dyn World) -> Html {
+        let mut scene_names: Vec<&String> = world
+            .characters()
+            .values()
+            .filter_map(|character| character.scene().as_ref())
+            .collect();
+        scene_names.sort();
+        scene_names.dedup();
+
+        let render_scene = |scene_name: &String| {
+            let scene = world.scenes().get(scene_name).unwrap();
+            html! {
+                <section class="section">
+                    <h1 class="title is-1">{ scene.short(world) }</h1>
+                    <article class="message">
+                        <div class="message-body">
+                            { scene.long(world) }
+                        </div>
+                    </article>
+                </section>
+            }
+        };
+
+        html! {
+            <div class="hero-body is-flex-direction-column is-align-items-stretch">
+                { for scene_names.into_iter().map(render_scene) }
+            </div>
+        }
+    }
+
     fn view_editor(&self, ctx: &Context<Self>, world: &dyn World) -> Html {
         let close = ctx.link().callback(|_| Msg::ShowEditor(false));
         let update_location = ctx
@@ -1390,6 +1882,62 @@ impl App {
         }
     }
 
+    fn show_error(&self, code: protocol::ErrorCode) {
+        if let Some(scope) = self.messages_scope.as_ref().borrow().as_ref() {
+            let message = MessageItem::new(
+                translations::get_message_global("error", &self.lang, None),
+                translations::get_message_global(code.msgid(), &self.lang, None),
+                MessageKind::Danger,
+                Some("fas fa-exclamation-triangle".to_string()),
+            );
+            scope.send_message(MessagesMsg::AddMessage(Rc::new(message)));
+        }
+    }
+
+    /// Owner joins the world as the narrator
+    fn set_secrets(&mut self, secrets: Option<protocol::JoinSecrets>) {
+        if let Some(secrets) = secrets.as_ref() {
+            storage::LocalStorage::set("secret", &secrets.narrator).unwrap();
+            self.secret = Some(secrets.narrator.clone());
+        }
+        self.secrets = secrets;
+    }
+
+    fn show_notice(&self, msgid: &str) {
+        if let Some(scope) = self.messages_scope.as_ref().borrow().as_ref() {
+            let message = MessageItem::new(
+                translations::get_message_global("owner", &self.lang, None),
+                translations::get_message_global(msgid, &self.lang, None),
+                MessageKind::Info,
+                Some("fas fa-people-arrows".to_string()),
+            );
+            scope.send_message(MessagesMsg::AddMessage(Rc::new(message)));
+        }
+    }
+
+    /// Worlds stored by older versions don't have any secrets
+    async fn ensure_secrets(
+        db: &rexie::Rexie,
+        record: database::StoredWorld,
+    ) -> protocol::JoinSecrets {
+        if let Some(secrets) = record.secrets {
+            secrets
+        } else {
+            let secrets = protocol::JoinSecrets::generate();
+            database::put_world(
+                db,
+                database::StoredWorld {
+                    secret: Some(secrets.narrator.clone()),
+                    secrets: Some(secrets.clone()),
+                    ..record
+                },
+            )
+            .await
+            .unwrap();
+            secrets
+        }
+    }
+
     fn request_to_get_world(&mut self, ctx: &Context<Self>, world_id: Uuid) {
         *self.loading.borrow_mut() = true;
         let name = ctx.props().name.clone();
@@ -1407,8 +1955,11 @@ impl App {
                     let db = database::init_database(&name).await;
                     if let Some(record) = database::get_world(&db, &world_id).await.unwrap() {
                         // First try to get world from database
-                        world.load(record.data).unwrap();
-                        link.send_message(Msg::SetWorldName(record.name));
+                        world.load(record.data.clone()).unwrap();
+                        link.send_message(Msg::SetWorldName(record.name.clone()));
+                        link.send_message(Msg::SetSecrets(Some(
+                            App::ensure_secrets(&db, record).await,
+                        )));
 
                         // If this world is owned update it right away
                         log::debug!("World {} is owned. Local db queried.", world_id);
@@ -1427,8 +1978,11 @@ impl App {
                     if let Some(record) = database::get_world(&db, &world_id).await.unwrap() {
                         if record.owned {
                             // First try to get world from database
-                            world.load(record.data).unwrap();
-                            link.send_message(Msg::SetWorldName(record.name));
+                            world.load(record.data.clone()).unwrap();
+                            link.send_message(Msg::SetWorldName(record.name.clone()));
+                            link.send_message(Msg::SetSecrets(Some(
+                                App::ensure_secrets(&db, record).await,
+                            )));
 
                             // If this world is owned update it right away
                             log::debug!("World {} is owned. Local db queried.", world_id);
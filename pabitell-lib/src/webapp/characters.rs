@@ -12,17 +12,34 @@ pub struct Character {
     pub icon: Rc<String>,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct CharacterQRJson {
     pub character: Option<String>,
     pub world_id: Uuid,
+    /// Secret which is required by the server to join the world
+    #[serde(default)]
+    pub secret: Option<String>,
+    /// Joins the world in read-only mode
+    #[serde(default)]
+    pub spectator: bool,
 }
 
 impl CharacterQRJson {
-    pub fn new(character: Option<String>, world_id: Uuid) -> Self {
+    pub fn new(character: Option<String>, world_id: Uuid, secret: Option<String>) -> Self {
         Self {
             character,
             world_id,
+            secret,
+            spectator: false,
+        }
+    }
+
+    pub fn spectator(world_id: Uuid, secret: Option<String>) -> Self {
+        Self {
+            character: None,
+            world_id,
+            secret,
+            spectator: true,
         }
     }
 }
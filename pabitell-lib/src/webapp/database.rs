@@ -7,6 +7,8 @@ use serde_json::Value;
 use uuid::Uuid;
 use wasm_bindgen::JsValue;
 
+use crate::protocol::JoinSecrets;
+
 #[derive(Serialize, Deserialize, Clone)]
 pub struct StoredWorld {
     pub id: Uuid,
@@ -17,6 +19,15 @@ pub struct StoredWorld {
     pub version: usize,
     pub owned: bool,
     pub name: Option<String>,
+    /// Secret which is used to join the world
+    #[serde(default)]
+    pub secret: Option<String>,
+    /// All join secrets (available only to the owner)
+    #[serde(default)]
+    pub secrets: Option<JoinSecrets>,
+    /// World was joined in read-only mode
+    #[serde(default)]
+    pub spectator: bool,
 }
 
 async fn _init_database(name: &str) -> Result<Rexie> {
@@ -94,9 +105,11 @@ pub async fn get_worlds(rex: &Rexie) -> Result<Vec<StoredWorld>> {
 pub async fn put_world(rex: &Rexie, mut stored_world: StoredWorld) -> Result<()> {
     log::debug!("DB Put world '{}'", &stored_world.id);
 
-    // Test whether world is present and if so keep owned attr
+    // Test whether world is present and if so keep owned attr and secrets
     if let Some(world) = get_world(rex, &stored_world.id).await? {
         stored_world.owned = world.owned;
+        stored_world.secret = stored_world.secret.or(world.secret);
+        stored_world.secrets = stored_world.secrets.or(world.secrets);
     }
 
     let transaction = rex.transaction(&["worlds"], TransactionMode::ReadWrite)?;
@@ -109,6 +122,23 @@ pub async fn put_world(rex: &Rexie, mut stored_world: StoredWorld) -> Result<()>
     Ok(())
 }
 
+pub async fn set_owned(rex: &Rexie, id: &Uuid, owned: bool) -> Result<()> {
+    log::debug!("DB set owned '{id}' - '{owned}'");
+
+    // put_world keeps the owned attr so it needs to be updated here
+    if let Some(mut stored_world) = get_world(rex, id).await? {
+        stored_world.owned = owned;
+        let transaction = rex.transaction(&["worlds"], TransactionMode::ReadWrite)?;
+        let worlds = transaction.store("worlds")?;
+        let record = serde_json::to_value(&stored_world).unwrap();
+        let value: JsValue = <JsValue as JsValueSerdeExt>::from_serde(&record).unwrap();
+        worlds.put(&value, None).await?;
+        transaction.done().await?;
+    }
+
+    Ok(())
+}
+
 pub async fn del_world(rex: &Rexie, id: &Uuid) -> Result<()> {
     log::debug!("DB delete world '{id}'");
     let transaction = rex.transaction(&["worlds"], TransactionMode::ReadWrite)?;
@@ -40,7 +40,7 @@ pub struct Props {
     pub available_characters: Rc<Vec<Rc<characters::Character>>>,
     pub story_name: String,
     pub story_detail: String,
-    pub character_scanned: Callback<(Option<String>, bool, Uuid)>,
+    pub character_scanned: Callback<(characters::CharacterQRJson, bool)>,
     pub lang: String,
     pub world_version: usize,
 }
@@ -53,7 +53,7 @@ pub enum Msg {
     ShowPrint,
     UpdateWorlds(Vec<database::StoredWorld>),
     WorldDelete(Uuid),
-    WorldPicked(Uuid, String, bool),
+    WorldPicked(characters::CharacterQRJson, bool),
     SetLanguage(String),
     DownloadWorld(Uuid, Value),
     ShowUpload,
@@ -106,21 +106,15 @@ impl Component for Intro {
                         Ok((data, _)) => {
                             match serde_json::from_slice::<characters::CharacterQRJson>(&data[..]) {
                                 Ok(character_json) => {
-                                    let characters::CharacterQRJson {
-                                        character,
-                                        world_id,
-                                    } = character_json;
                                     log::debug!(
                                         "Joining world (id={:?}) as character {:?}",
-                                        &world_id,
-                                        &character
+                                        &character_json.world_id,
+                                        &character_json.character
                                     );
-                                    let fixed_character = character.is_some();
-                                    ctx.props().character_scanned.emit((
-                                        character,
-                                        fixed_character,
-                                        world_id,
-                                    ));
+                                    let fixed_character = character_json.character.is_some();
+                                    ctx.props()
+                                        .character_scanned
+                                        .emit((character_json, fixed_character));
                                 }
                                 Err(err) => {
                                     log::warn!("Error while processing data: {:?}", err);
@@ -144,16 +138,10 @@ impl Component for Intro {
                 }
                 false
             }
-            Msg::WorldPicked(id, character, fixed_character) => {
-                ctx.props().character_scanned.emit((
-                    if character == "narrator" {
-                        None
-                    } else {
-                        Some(character)
-                    },
-                    fixed_character,
-                    id,
-                ));
+            Msg::WorldPicked(character_json, fixed_character) => {
+                ctx.props()
+                    .character_scanned
+                    .emit((character_json, fixed_character));
                 true
             }
             Msg::WorldDelete(id) => {
@@ -305,7 +293,7 @@ impl Component for Intro {
                             .unwrap_or_else(|| "narrator".to_string()),
                     )
                     .unwrap();
-                let characters::Character { name, icon, .. } = character.as_ref().clone();
+                let characters::Character { icon, .. } = character.as_ref().clone();
                 let world_id = stored_world.id.clone();
                 let world_id_cloned = world_id.clone();
                 let name_input_ref = NodeRef::default();
@@ -320,8 +308,14 @@ impl Component for Intro {
                     classes!("button", "is-small", "is-info", "is-outlined")
                 };
                 let events = stored_world.data["event_count"].as_u64().unwrap();
+                let character_json = characters::CharacterQRJson {
+                    character: stored_world.character.clone(),
+                    world_id,
+                    secret: stored_world.secret.clone(),
+                    spectator: stored_world.spectator,
+                };
                 let restore_cb = cloned_link.callback(move |_| {
-                    Msg::WorldPicked(world_id, name.to_string(), stored_world.fixed_character)
+                    Msg::WorldPicked(character_json.clone(), stored_world.fixed_character)
                 });
                 let delete_cb =
                     cloned_link.callback(move |_| Msg::WorldDelete(stored_world.id.clone()));
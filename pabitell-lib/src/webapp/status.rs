@@ -7,11 +7,13 @@ pub struct Props {
     pub reset_world: Callback<()>,
     pub leave_world: Callback<()>,
     pub edit_world: Callback<()>,
+    pub hand_over_world: Callback<()>,
     pub event_count: usize,
     pub status: WsStatus,
     pub ws_request_failed: bool,
     pub can_reset: bool,
     pub can_edit: bool,
+    pub can_hand_over: bool,
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -54,6 +56,7 @@ pub enum Msg {
     ResetWorld,
     LeaveWorld,
     EditWorld,
+    HandOverWorld,
 }
 
 impl Component for Status {
@@ -77,6 +80,9 @@ impl Component for Status {
             Msg::EditWorld => {
                 ctx.props().edit_world.emit(());
             }
+            Msg::HandOverWorld => {
+                ctx.props().hand_over_world.emit(());
+            }
         }
         true
     }
@@ -117,6 +123,19 @@ impl Component for Status {
             html! {}
         };
 
+        let hand_over_part = if ctx.props().can_hand_over {
+            let hand_over_world_cb = link.callback(|_| Msg::HandOverWorld);
+            html! {
+                <button class="button is-outlined is-medium" onclick={hand_over_world_cb}>
+                    <span class="icon has-text-info">
+                        <i class="fas fa-people-arrows"></i>
+                    </span>
+                </button>
+            }
+        } else {
+            html! {}
+        };
+
         let status_part = if !ctx.props().ws_request_failed {
             html! {
                 <button class="button is-outlined is-medium" {onclick}>
@@ -153,6 +172,7 @@ impl Component for Status {
                 </button>
                 { reset_part }
                 { edit_part }
+                { hand_over_part }
             </>
         }
     }
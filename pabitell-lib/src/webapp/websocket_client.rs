@@ -11,6 +11,8 @@ use uuid::Uuid;
 use wasm_bindgen_futures::spawn_local;
 use yew::{html, prelude::*};
 
+use crate::protocol;
+
 #[derive(Clone, Debug, Properties)]
 pub struct Props {
     pub namespace: String,
@@ -37,13 +39,15 @@ impl PartialEq<Self> for Props {
 
 pub struct WebsocketClient {
     world_id: Option<Uuid>,
+    /// Secret which is sent to the server when connecting
+    secret: Option<String>,
     sender: Option<(SplitSink<WebSocket, Message>, Trigger)>,
     queued_messages: Vec<String>,
     reconnect_timeout: Option<Timeout>,
 }
 
 pub enum Msg {
-    Connect(Uuid),
+    Connect(Uuid, Option<String>),
     SetSender(SplitSink<WebSocket, Message>, Trigger, bool),
     /// Disconnected by e.g. failed connection
     Disconnected,
@@ -60,22 +64,25 @@ impl Component for WebsocketClient {
         let link = ctx.link().clone();
         let props = ctx.props();
         match msg {
-            Msg::Connect(world_id) => {
+            Msg::Connect(world_id, secret) => {
                 // Disconnect first
                 if self.world_id.is_some() {
                     link.send_message(Msg::Disconnect);
-                    link.send_message(Msg::Connect(world_id));
+                    link.send_message(Msg::Connect(world_id, secret));
                     return true;
                 }
                 self.world_id = Some(world_id);
+                self.secret = secret;
                 log::debug!("Connecting to {:?}", &world_id);
                 if let Some(url) = self.ws_url(ctx) {
+                    // secret is not a part of the url so it doesn't end up in logs
+                    let protocols = protocol::ws_protocols(self.secret.as_deref());
                     props.connecting.emit(());
                     let props = props.clone();
                     spawn_local(async move {
                         let mut error = false;
                         let _ = &props;
-                        match WebSocket::open(&url) {
+                        match WebSocket::open_with_protocols(&url, &protocols) {
                             Ok(ws) => {
                                 log::debug!("WS opened {}", url);
                                 // Terminate channel
@@ -199,6 +206,7 @@ impl Component for WebsocketClient {
             queued_messages: vec![],
             reconnect_timeout: None,
             world_id: None,
+            secret: None,
         }
     }
 
@@ -216,8 +224,9 @@ impl Component for WebsocketClient {
 impl WebsocketClient {
     fn plan_reconnect(&mut self, ctx: &Context<Self>, world_id: Uuid) {
         let link = ctx.link().to_owned();
+        let secret = self.secret.clone();
         self.reconnect_timeout = Some(Timeout::new(5000, move || {
-            link.send_message(Msg::Connect(world_id));
+            link.send_message(Msg::Connect(world_id, secret));
         }));
     }
 
@@ -0,0 +1,249 @@
+use actix::Addr;
+use actix_web::{
+    error, get,
+    http::{header::AUTHORIZATION, StatusCode},
+    post,
+    rt::time::timeout,
+    web, Error, HttpRequest, HttpResponse,
+};
+use pabitell_lib::protocol;
+use serde_json::{json, Value};
+use std::time::Duration;
+use uuid::Uuid;
+
+use crate::websocket::{
+    Access, Authorize, ForwardRequest, GetEvents, ListWorlds, Timeouts, WorldKey, WsManager,
+};
+
+/// Manager responds with a timeout error on its own, this is just a safety net
+const TIMEOUT_MARGIN: Duration = Duration::from_secs(1);
+
+/// Token which grants access to the admin endpoints
+/// (the endpoints are disabled when it is not set)
+#[derive(Debug, Clone, Default)]
+pub struct AdminToken(pub Option<String>);
+
+impl AdminToken {
+    /// Checks `Authorization: Bearer <token>` header
+    fn authorize(&self, req: &HttpRequest) -> Result<(), Error> {
+        let expected = self
+            .0
+            .as_ref()
+            .ok_or_else(|| error::ErrorNotFound("admin endpoints are disabled"))?;
+        let token = req
+            .headers()
+            .get(AUTHORIZATION)
+            .and_then(|e| e.to_str().ok())
+            .and_then(|e| e.strip_prefix("Bearer "))
+            .ok_or_else(|| error::ErrorUnauthorized("missing admin token"))?;
+        if constant_time_eq(token.as_bytes(), expected.as_bytes()) {
+            Ok(())
+        } else {
+            Err(error::ErrorForbidden("invalid admin token"))
+        }
+    }
+}
+
+/// Compares tokens without leaking the length of the matching prefix
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Checks the join secret the same way as the websocket endpoint does
+/// (the secret is passed in a header so it doesn't end up in access logs)
+async fn authorize(
+    manager: &Addr<WsManager>,
+    req: &HttpRequest,
+    key: WorldKey,
+) -> Result<Access, Error> {
+    let secret = req
+        .headers()
+        .get(protocol::SECRET_HEADER)
+        .and_then(|e| e.to_str().ok())
+        .map(String::from);
+    let access = manager
+        .send(Authorize { key, secret })
+        .await
+        .map_err(error::ErrorInternalServerError)?;
+    if access == Access::Denied {
+        Err(error::ErrorForbidden("invalid secret"))
+    } else {
+        Ok(access)
+    }
+}
+
+/// Sends the request to the owner of the world and waits for the response
+async fn ask_owner(
+    manager: &Addr<WsManager>,
+    timeouts: &Timeouts,
+    key: WorldKey,
+    request: protocol::RequestMessage,
+) -> Result<protocol::ResponseMessage, Error> {
+    let receiver = manager
+        .send(ForwardRequest { key, request })
+        .await
+        .map_err(error::ErrorInternalServerError)?
+        .ok_or_else(|| error::ErrorNotFound("world is not active"))?;
+
+    match timeout(timeouts.request_timeout + TIMEOUT_MARGIN, receiver).await {
+        Ok(Ok(protocol::Message::Response(response))) => Ok(response),
+        Ok(Ok(_)) => Err(error::ErrorBadGateway("unexpected message")),
+        Ok(Err(_)) => Err(error::ErrorServiceUnavailable("request was dropped")),
+        Err(_) => Err(error::ErrorGatewayTimeout("owner did not respond")),
+    }
+}
+
+fn world_key((namespace, story, world_id): (String, String, Uuid)) -> WorldKey {
+    WorldKey {
+        namespace,
+        story,
+        world_id,
+    }
+}
+
+fn error_response(error: protocol::ErrorResponse) -> HttpResponse {
+    let status = match error.code {
+        protocol::ErrorCode::UnknownWorld => StatusCode::NOT_FOUND,
+        protocol::ErrorCode::ParseError | protocol::ErrorCode::InvalidMessage => {
+            StatusCode::BAD_REQUEST
+        }
+        protocol::ErrorCode::AccessDenied => StatusCode::FORBIDDEN,
+        protocol::ErrorCode::NotOwner => StatusCode::SERVICE_UNAVAILABLE,
+        protocol::ErrorCode::Timeout => StatusCode::GATEWAY_TIMEOUT,
+        protocol::ErrorCode::EventNotAvailable
+        | protocol::ErrorCode::VersionMismatch
+        | protocol::ErrorCode::NoCandidate => StatusCode::CONFLICT,
+    };
+    HttpResponse::build(status).json(error)
+}
+
+/// Ids of the worlds with connected clients (requires the admin token)
+#[get("/api/{namespace}/{story}/worlds/")]
+pub async fn list_worlds(
+    req: HttpRequest,
+    data: web::Data<Addr<WsManager>>,
+    admin: web::Data<AdminToken>,
+    path: web::Path<(String, String)>,
+) -> Result<HttpResponse, Error> {
+    admin.authorize(&req)?;
+    let (namespace, story) = path.into_inner();
+    let worlds = data
+        .send(ListWorlds { namespace, story })
+        .await
+        .map_err(error::ErrorInternalServerError)?;
+    Ok(HttpResponse::Ok().json(worlds))
+}
+
+/// Current state of the world obtained from its owner
+#[get("/api/{namespace}/{story}/worlds/{world}/")]
+pub async fn get_world(
+    req: HttpRequest,
+    data: web::Data<Addr<WsManager>>,
+    timeouts: web::Data<Timeouts>,
+    path: web::Path<(String, String, Uuid)>,
+) -> Result<HttpResponse, Error> {
+    let key = world_key(path.into_inner());
+    let world_id = key.world_id;
+    authorize(&data, &req, key.clone()).await?;
+
+    let request = protocol::RequestMessage::GetWorld(protocol::GetWorldRequest {
+        msg_id: Uuid::new_v4(),
+        world_id,
+    });
+    match ask_owner(&data, &timeouts, key, request).await? {
+        protocol::ResponseMessage::GetWorld(protocol::GetWorldResponse {
+            world: Some(world),
+            ..
+        }) => Ok(HttpResponse::Ok().json(world)),
+        protocol::ResponseMessage::GetWorld(_) => Err(error::ErrorNotFound("world not found")),
+        protocol::ResponseMessage::Error(error) => Ok(error_response(error)),
+        _ => Err(error::ErrorBadGateway("unexpected response")),
+    }
+}
+
+/// Events which were triggered since the server was started
+#[get("/api/{namespace}/{story}/worlds/{world}/events/")]
+pub async fn get_events(
+    req: HttpRequest,
+    data: web::Data<Addr<WsManager>>,
+    path: web::Path<(String, String, Uuid)>,
+) -> Result<HttpResponse, Error> {
+    let key = world_key(path.into_inner());
+    authorize(&data, &req, key.clone()).await?;
+
+    let events = data
+        .send(GetEvents { key })
+        .await
+        .map_err(error::ErrorInternalServerError)?;
+    Ok(HttpResponse::Ok().json(events))
+}
+
+/// Triggers an event in the world
+#[post("/api/{namespace}/{story}/worlds/{world}/events/")]
+pub async fn post_event(
+    req: HttpRequest,
+    data: web::Data<Addr<WsManager>>,
+    timeouts: web::Data<Timeouts>,
+    path: web::Path<(String, String, Uuid)>,
+    event: web::Json<Value>,
+) -> Result<HttpResponse, Error> {
+    let key = world_key(path.into_inner());
+    let world_id = key.world_id;
+    let access = authorize(&data, &req, key.clone()).await?;
+    if access == Access::Granted(protocol::Role::Spectator) {
+        return Err(error::ErrorForbidden("spectators can't trigger events"));
+    }
+
+    let request = protocol::RequestMessage::TriggerEvent(protocol::TriggerEventRequest {
+        msg_id: Uuid::new_v4(),
+        event: event.into_inner(),
+        world_id,
+    });
+    match ask_owner(&data, &timeouts, key, request).await? {
+        protocol::ResponseMessage::TriggerEvent(response) => {
+            Ok(HttpResponse::Ok().json(json!({ "success": response.success })))
+        }
+        protocol::ResponseMessage::Error(error) => Ok(error_response(error)),
+        _ => Err(error::ErrorBadGateway("unexpected response")),
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::AdminToken;
+    use actix_web::{http::StatusCode, test::TestRequest};
+
+    #[test]
+    fn admin_token() {
+        let status = |token: &AdminToken, req: TestRequest| {
+            token
+                .authorize(&req.to_http_request())
+                .map_err(|err| err.as_response_error().status_code())
+        };
+        let disabled = AdminToken::default();
+        assert_eq!(
+            status(&disabled, TestRequest::default()),
+            Err(StatusCode::NOT_FOUND)
+        );
+
+        let token = AdminToken(Some("s3cret".to_string()));
+        assert_eq!(
+            status(&token, TestRequest::default()),
+            Err(StatusCode::UNAUTHORIZED)
+        );
+        assert_eq!(
+            status(
+                &token,
+                TestRequest::default().insert_header(("Authorization", "Bearer wrong"))
+            ),
+            Err(StatusCode::FORBIDDEN)
+        );
+        assert_eq!(
+            status(
+                &token,
+                TestRequest::default().insert_header(("Authorization", "Bearer s3cret"))
+            ),
+            Ok(())
+        );
+    }
+}
@@ -0,0 +1,505 @@
+//! Simulates clients of the websocket server
+//!
+//! Each world gets one owner which answers the requests and a group of players
+//! which keep sending `GetWorld` and `TriggerEvent` requests with random payloads.
+
+use anyhow::{anyhow, Result};
+use clap::{crate_authors, crate_version, App, Arg, ArgMatches};
+use futures::{SinkExt, StreamExt};
+use pabitell_lib::protocol;
+use rand::{distributions::Alphanumeric, Rng};
+use serde_json::json;
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    str::FromStr,
+    time::{Duration, Instant},
+};
+use tokio::{net::TcpStream, time};
+use tokio_tungstenite::{tungstenite, MaybeTlsStream, WebSocketStream};
+use uuid::Uuid;
+
+type Socket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+/// Requests per second of a single client (the ticker needs a non-zero period)
+const MAX_RATE: f64 = 1000.0;
+
+#[derive(Debug, Clone)]
+struct Config {
+    url: String,
+    namespace: String,
+    story: String,
+    worlds: usize,
+    clients: usize,
+    /// Requests per second sent by a single player
+    rate: f64,
+    duration: Duration,
+    /// How long to wait for a response
+    timeout: Duration,
+    /// Size of the random part of the event payload
+    payload_size: usize,
+}
+
+fn parse<T>(matches: &ArgMatches, name: &str) -> Result<T>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    let value = matches.value_of(name).unwrap();
+    value
+        .parse()
+        .map_err(|err| anyhow!("Invalid value of '{}': {}", name, err))
+}
+
+/// Positive and finite number of seconds
+fn parse_seconds(matches: &ArgMatches, name: &str) -> Result<Duration> {
+    let seconds: f64 = parse(matches, name)?;
+    if !seconds.is_finite() || seconds <= 0.0 {
+        return Err(anyhow!("Value of '{}' has to be a positive number", name));
+    }
+    Duration::try_from_secs_f64(seconds)
+        .map_err(|err| anyhow!("Invalid value of '{}': {}", name, err))
+}
+
+impl Config {
+    fn from_matches(matches: &ArgMatches) -> Result<Self> {
+        let config = Self {
+            url: matches
+                .value_of("url")
+                .unwrap()
+                .trim_end_matches('/')
+                .to_string(),
+            namespace: matches.value_of("namespace").unwrap().to_string(),
+            story: matches.value_of("story").unwrap().to_string(),
+            worlds: parse(matches, "worlds")?,
+            clients: parse(matches, "clients")?,
+            rate: parse(matches, "rate")?,
+            duration: parse_seconds(matches, "duration")?,
+            timeout: parse_seconds(matches, "timeout")?,
+            payload_size: parse(matches, "payload-size")?,
+        };
+        if config.worlds == 0 || config.clients < config.worlds {
+            return Err(anyhow!("Each world needs at least one client"));
+        }
+        if !config.rate.is_finite() || config.rate <= 0.0 {
+            return Err(anyhow!("Rate has to be positive"));
+        }
+        if config.rate > MAX_RATE {
+            return Err(anyhow!("Rate can't be higher than {}", MAX_RATE));
+        }
+        Ok(config)
+    }
+
+    fn world_url(&self, world_id: Uuid) -> String {
+        format!(
+            "{}/ws/{}/{}/{}/",
+            self.url, self.namespace, self.story, world_id
+        )
+    }
+}
+
+/// Results of a single client
+#[derive(Debug, Default)]
+struct Stats {
+    connected: usize,
+    failed_connections: usize,
+    requests: usize,
+    latencies: Vec<Duration>,
+    /// Error responses by code
+    errors: HashMap<String, usize>,
+    /// Requests without a response
+    unanswered: usize,
+    /// Event notifications which were not delivered
+    missed_events: usize,
+    /// Closed connections by reason
+    closed: HashMap<String, usize>,
+}
+
+impl Stats {
+    fn merge(&mut self, other: Stats) {
+        self.connected += other.connected;
+        self.failed_connections += other.failed_connections;
+        self.requests += other.requests;
+        self.latencies.extend(other.latencies);
+        for (code, count) in other.errors {
+            *self.errors.entry(code).or_default() += count;
+        }
+        self.unanswered += other.unanswered;
+        self.missed_events += other.missed_events;
+        for (reason, count) in other.closed {
+            *self.closed.entry(reason).or_default() += count;
+        }
+    }
+
+    fn close(&mut self, reason: String) {
+        *self.closed.entry(reason).or_default() += 1;
+    }
+
+    fn percentile(sorted: &[Duration], percentile: f64) -> Duration {
+        if sorted.is_empty() {
+            return Duration::ZERO;
+        }
+        let idx = ((sorted.len() - 1) as f64 * percentile / 100.0).round() as usize;
+        sorted[idx]
+    }
+
+    fn report(mut self, config: &Config, elapsed: Duration) {
+        self.latencies.sort();
+        let answered = self.latencies.len();
+        println!(
+            "clients: {} connected, {} failed",
+            self.connected, self.failed_connections
+        );
+        println!("worlds: {}", config.worlds);
+        println!(
+            "requests: {} sent, {} answered ({:.1}/s)",
+            self.requests,
+            answered,
+            answered as f64 / elapsed.as_secs_f64()
+        );
+        println!(
+            "latency: p50={:?} p90={:?} p99={:?} max={:?}",
+            Self::percentile(&self.latencies, 50.0),
+            Self::percentile(&self.latencies, 90.0),
+            Self::percentile(&self.latencies, 99.0),
+            self.latencies.last().copied().unwrap_or_default(),
+        );
+        let mut errors: Vec<_> = self.errors.into_iter().collect();
+        errors.sort();
+        for (code, count) in errors {
+            println!("error: {} {}", code, count);
+        }
+        println!(
+            "dropped: {} unanswered requests, {} missed events",
+            self.unanswered, self.missed_events
+        );
+        let mut closed: Vec<_> = self.closed.into_iter().collect();
+        closed.sort();
+        for (reason, count) in closed {
+            println!("closed: {} {}", reason, count);
+        }
+    }
+}
+
+async fn connect(config: &Config, world_id: Uuid) -> Result<Socket> {
+    let (socket, _) = tokio_tungstenite::connect_async(config.world_url(world_id)).await?;
+    Ok(socket)
+}
+
+async fn send(socket: &mut Socket, message: protocol::Message) -> Result<()> {
+    socket
+        .send(tungstenite::Message::Text(serde_json::to_string(&message)?))
+        .await?;
+    Ok(())
+}
+
+/// Parses the next protocol message (reason is returned when the connection is closed)
+async fn recv(socket: &mut Socket) -> Result<protocol::Message, String> {
+    while let Some(message) = socket.next().await {
+        match message.map_err(|err| err.to_string())? {
+            tungstenite::Message::Text(text) => {
+                if let Ok(message) = serde_json::from_str(&text) {
+                    return Ok(message);
+                }
+            }
+            tungstenite::Message::Close(Some(frame)) => {
+                return Err(format!("{} {}", u16::from(frame.code), frame.reason));
+            }
+            tungstenite::Message::Close(None) => break,
+            // pings are answered by tungstenite
+            _ => {}
+        }
+    }
+    Err("connection closed".to_string())
+}
+
+/// Claims the world and answers the requests of the players
+async fn owner(config: Config, world_id: Uuid, ready: tokio::sync::oneshot::Sender<()>) -> Stats {
+    let mut stats = Stats::default();
+    let mut socket = match connect(&config, world_id).await {
+        Ok(socket) => socket,
+        Err(err) => {
+            eprintln!("owner of {} failed to connect: {}", world_id, err);
+            stats.failed_connections += 1;
+            return stats;
+        }
+    };
+    stats.connected += 1;
+    let claim = protocol::ClaimOwnershipRequest {
+        msg_id: Uuid::new_v4(),
+        world_id,
+        secrets: None,
+    };
+    if let Err(err) = send(
+        &mut socket,
+        protocol::Message::Request(protocol::RequestMessage::ClaimOwnership(claim)),
+    )
+    .await
+    {
+        stats.close(err.to_string());
+        return stats;
+    }
+    let _ = ready.send(());
+
+    let mut event_count = 0;
+    let deadline = time::sleep(config.duration + config.timeout * 2);
+    tokio::pin!(deadline);
+    loop {
+        let message = tokio::select! {
+            message = recv(&mut socket) => message,
+            _ = &mut deadline => break,
+        };
+        let response = match message {
+            Ok(protocol::Message::Request(protocol::RequestMessage::GetWorld(request))) => {
+                protocol::Message::Response(protocol::ResponseMessage::GetWorld(
+                    protocol::GetWorldResponse {
+                        msg_id: request.msg_id,
+                        world: Some(json!({ "event_count": event_count })),
+                    },
+                ))
+            }
+            Ok(protocol::Message::Request(protocol::RequestMessage::TriggerEvent(request))) => {
+                event_count += 1;
+                let notification =
+                    protocol::NotificationMessage::Event(protocol::EventNotification {
+                        event: request.event,
+                        event_count,
+                    });
+                if let Err(err) =
+                    send(&mut socket, protocol::Message::Notification(notification)).await
+                {
+                    stats.close(err.to_string());
+                    break;
+                }
+                protocol::Message::Response(protocol::ResponseMessage::TriggerEvent(
+                    protocol::TriggerEventResponse {
+                        msg_id: request.msg_id,
+                        success: true,
+                    },
+                ))
+            }
+            Ok(protocol::Message::Response(protocol::ResponseMessage::ClaimOwnership(
+                response,
+            ))) => {
+                if !response.granted {
+                    eprintln!("ownership of {} was not granted", world_id);
+                }
+                continue;
+            }
+            Ok(_) => continue,
+            Err(reason) => {
+                stats.close(reason);
+                break;
+            }
+        };
+        if let Err(err) = send(&mut socket, response).await {
+            stats.close(err.to_string());
+            break;
+        }
+    }
+    let _ = socket.close(None).await;
+    stats
+}
+
+fn random_request(config: &Config, world_id: Uuid) -> protocol::RequestMessage {
+    let mut rng = rand::thread_rng();
+    let msg_id = Uuid::new_v4();
+    if rng.gen_bool(0.5) {
+        protocol::RequestMessage::GetWorld(protocol::GetWorldRequest { msg_id, world_id })
+    } else {
+        let padding: String = (&mut rng)
+            .sample_iter(&Alphanumeric)
+            .take(config.payload_size)
+            .map(char::from)
+            .collect();
+        protocol::RequestMessage::TriggerEvent(protocol::TriggerEventRequest {
+            msg_id,
+            event: json!({
+                "name": "load_test",
+                "value": rng.gen::<u32>(),
+                "padding": padding,
+            }),
+            world_id,
+        })
+    }
+}
+
+/// Sends requests with the given rate and measures the responses
+async fn player(config: Config, world_id: Uuid) -> Stats {
+    let mut stats = Stats::default();
+    let mut socket = match connect(&config, world_id).await {
+        Ok(socket) => socket,
+        Err(err) => {
+            eprintln!("player of {} failed to connect: {}", world_id, err);
+            stats.failed_connections += 1;
+            return stats;
+        }
+    };
+    stats.connected += 1;
+
+    let mut pending: HashMap<Uuid, Instant> = HashMap::new();
+    let mut last_event: Option<usize> = None;
+    let mut ticker = time::interval(Duration::from_secs_f64(1.0 / config.rate));
+    let end = Instant::now() + config.duration;
+    let drain = time::sleep(config.duration + config.timeout);
+    tokio::pin!(drain);
+    loop {
+        tokio::select! {
+            _ = ticker.tick(), if Instant::now() < end => {
+                let request = random_request(&config, world_id);
+                pending.insert(request.msg_id(), Instant::now());
+                stats.requests += 1;
+                if let Err(err) = send(&mut socket, protocol::Message::Request(request)).await {
+                    stats.close(err.to_string());
+                    break;
+                }
+            }
+            message = recv(&mut socket) => match message {
+                Ok(protocol::Message::Response(response)) => {
+                    let sent = response.msg_id().and_then(|msg_id| pending.remove(&msg_id));
+                    if let Some(sent) = sent {
+                        if let protocol::ResponseMessage::Error(error) = response {
+                            *stats.errors.entry(format!("{:?}", error.code)).or_default() += 1;
+                        } else {
+                            stats.latencies.push(sent.elapsed());
+                        }
+                    }
+                }
+                Ok(protocol::Message::Notification(protocol::NotificationMessage::Event(event))) => {
+                    if let Some(last) = last_event {
+                        stats.missed_events += event.event_count.saturating_sub(last + 1);
+                    }
+                    last_event = Some(event.event_count);
+                }
+                Ok(_) => {}
+                Err(reason) => {
+                    stats.close(reason);
+                    break;
+                }
+            },
+            _ = &mut drain => break,
+        }
+        if Instant::now() >= end && pending.is_empty() {
+            break;
+        }
+    }
+    stats.unanswered += pending.len();
+    let _ = socket.close(None).await;
+    stats
+}
+
+async fn run(config: Config) -> Stats {
+    let world_ids: Vec<Uuid> = (0..config.worlds).map(|_| Uuid::new_v4()).collect();
+
+    // owners need to claim the worlds before the players start
+    let mut owners = vec![];
+    let mut ready = vec![];
+    for world_id in &world_ids {
+        let (sender, receiver) = tokio::sync::oneshot::channel();
+        owners.push(tokio::spawn(owner(config.clone(), *world_id, sender)));
+        ready.push(receiver);
+    }
+    for receiver in ready {
+        let _ = receiver.await;
+    }
+    // give the server time to process the claims
+    time::sleep(Duration::from_millis(100)).await;
+
+    let players: Vec<_> = (0..config.clients - config.worlds)
+        .map(|idx| tokio::spawn(player(config.clone(), world_ids[idx % world_ids.len()])))
+        .collect();
+
+    let mut stats = Stats::default();
+    for handle in players.into_iter().chain(owners) {
+        match handle.await {
+            Ok(result) => stats.merge(result),
+            Err(err) => eprintln!("client failed: {}", err),
+        }
+    }
+    stats
+}
+
+fn main() {
+    let app = App::new("pabitell-loadtest")
+        .author(crate_authors!())
+        .version(crate_version!())
+        .about("Load test of pabitell-webserver")
+        .arg(
+            Arg::new("url")
+                .help("Websocket URL of the server")
+                .long("url")
+                .takes_value(true)
+                .default_value("ws://127.0.0.1:8080"),
+        )
+        .arg(
+            Arg::new("namespace")
+                .help("Namespace of the worlds")
+                .long("namespace")
+                .takes_value(true)
+                .default_value("default"),
+        )
+        .arg(
+            Arg::new("story")
+                .help("Story of the worlds")
+                .long("story")
+                .takes_value(true)
+                .default_value("loadtest"),
+        )
+        .arg(
+            Arg::new("worlds")
+                .help("Number of worlds")
+                .long("worlds")
+                .short('m')
+                .takes_value(true)
+                .default_value("10"),
+        )
+        .arg(
+            Arg::new("clients")
+                .help("Number of clients (including the owners of the worlds)")
+                .long("clients")
+                .short('n')
+                .takes_value(true)
+                .default_value("100"),
+        )
+        .arg(
+            Arg::new("rate")
+                .help("Requests per second sent by a single client (at most 1000)")
+                .long("rate")
+                .takes_value(true)
+                .default_value("1"),
+        )
+        .arg(
+            Arg::new("duration")
+                .help("How long to send the requests (in seconds)")
+                .long("duration")
+                .takes_value(true)
+                .default_value("30"),
+        )
+        .arg(
+            Arg::new("timeout")
+                .help("How long to wait for a response (in seconds)")
+                .long("timeout")
+                .takes_value(true)
+                .default_value("5"),
+        )
+        .arg(
+            Arg::new("payload-size")
+                .help("Size of the random payload of the events (in bytes)")
+                .long("payload-size")
+                .takes_value(true)
+                .default_value("64"),
+        );
+
+    let config = Config::from_matches(&app.get_matches()).unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        std::process::exit(1);
+    });
+
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+    let started = Instant::now();
+    let stats = runtime.block_on(run(config.clone()));
+    stats.report(&config, started.elapsed());
+}
@@ -0,0 +1,270 @@
+use actix::prelude::*;
+use pabitell_lib::protocol;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use tokio::sync::broadcast;
+use tracing::warn;
+use uuid::Uuid;
+
+use crate::websocket::WorldKey;
+
+/// Message which is passed between server instances
+#[derive(Message, Debug, Clone, Serialize, Deserialize)]
+#[rtype(result = "()")]
+pub struct Envelope {
+    /// Instance which published the message
+    pub origin: Uuid,
+    pub key: WorldKey,
+    pub relay: Relay,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "relay", rename_all = "snake_case")]
+pub enum Relay {
+    /// Message for all clients of the world
+    Broadcast { data: String },
+    /// Request for the owner of the world
+    Request { data: String },
+    /// Response to a request made on another instance
+    Response { msg_id: Uuid, data: String },
+    /// Secrets registered by the owner of the world
+    /// (accepted only from the instance of the owner or when no secrets are known)
+    Secrets { secrets: protocol::JoinSecrets },
+    /// Client of the origin wants to own the world
+    /// (instances with the owner object by announcing it)
+    Claim,
+    /// Client of the origin owns the world (announced periodically)
+    Owner,
+    /// Owner of the world left the origin
+    Released,
+}
+
+/// Shares messages of the worlds between server instances
+pub trait Broadcast: fmt::Debug {
+    /// Sends the envelope to other instances
+    fn publish(&self, envelope: Envelope);
+    /// Whether any other instance can recieve the envelopes
+    fn has_peers(&self) -> bool;
+    /// Starts delivering envelopes from other instances to the manager
+    /// (own envelopes may be delivered as well)
+    fn subscribe(&mut self, manager: Recipient<Envelope>);
+}
+
+/// Other server instances which share the worlds
+#[derive(Debug)]
+pub struct Peers {
+    /// Identifies this instance
+    instance: Uuid,
+    broadcast: Box<dyn Broadcast>,
+}
+
+impl Peers {
+    pub fn new(broadcast: Box<dyn Broadcast>) -> Self {
+        Self {
+            instance: Uuid::new_v4(),
+            broadcast,
+        }
+    }
+
+    pub fn publish(&self, key: &WorldKey, relay: Relay) {
+        self.broadcast.publish(Envelope {
+            origin: self.instance,
+            key: key.clone(),
+            relay,
+        });
+    }
+
+    pub fn subscribe(&mut self, manager: Recipient<Envelope>) {
+        self.broadcast.subscribe(manager);
+    }
+
+    pub fn has_peers(&self) -> bool {
+        self.broadcast.has_peers()
+    }
+
+    /// Identifies this instance
+    /// (ownership conflicts are won by the instance with the lower id)
+    pub fn instance(&self) -> Uuid {
+        self.instance
+    }
+
+    /// Whether the envelope was published by this instance
+    pub fn is_own(&self, envelope: &Envelope) -> bool {
+        envelope.origin == self.instance
+    }
+}
+
+/// How many envelopes can wait for a slow subscriber
+const CHANNEL_CAPACITY: usize = 1024;
+
+/// Broadcast within a single process
+/// clones share the same channel
+#[derive(Debug, Clone)]
+pub struct MemoryBroadcast {
+    sender: broadcast::Sender<Envelope>,
+}
+
+impl Default for MemoryBroadcast {
+    fn default() -> Self {
+        let (sender, _) = broadcast::channel(CHANNEL_CAPACITY);
+        Self { sender }
+    }
+}
+
+impl MemoryBroadcast {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Broadcast for MemoryBroadcast {
+    fn publish(&self, envelope: Envelope) {
+        // fails only when there are no subscribers
+        let _ = self.sender.send(envelope);
+    }
+
+    fn has_peers(&self) -> bool {
+        // the instance is subscribed as well
+        self.sender.receiver_count() > 1
+    }
+
+    fn subscribe(&mut self, manager: Recipient<Envelope>) {
+        let mut receiver = self.sender.subscribe();
+        actix::spawn(async move {
+            loop {
+                match receiver.recv().await {
+                    Ok(envelope) => manager.do_send(envelope),
+                    Err(broadcast::error::RecvError::Lagged(count)) => {
+                        warn!("broadcast_lagged;count={}", count);
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                }
+            }
+        });
+    }
+}
+
+#[cfg(unix)]
+pub use unix::UnixBus;
+
+#[cfg(unix)]
+mod unix {
+    use actix::Recipient;
+    use anyhow::Result;
+    use std::{
+        fs, io,
+        os::unix::net::UnixDatagram,
+        path::{Path, PathBuf},
+        thread,
+    };
+    use tracing::{debug, info, warn};
+    use uuid::Uuid;
+
+    use super::{Broadcast, Envelope};
+
+    /// Biggest envelope which can be recieved
+    /// (limited by the default socket buffer size on linux)
+    const MAX_DATAGRAM: usize = 200 * 1024;
+    const SOCKET_EXTENSION: &str = "sock";
+
+    /// Broadcast between processes on the same host
+    /// each instance binds a datagram socket within a shared directory
+    /// and sends envelopes to all other sockets found there
+    #[derive(Debug)]
+    pub struct UnixBus {
+        path: PathBuf,
+        dir: PathBuf,
+        /// Socket bound to `path` (taken when subscribed)
+        receiver: Option<UnixDatagram>,
+        sender: UnixDatagram,
+    }
+
+    impl UnixBus {
+        pub fn new(dir: &Path) -> Result<Self> {
+            fs::create_dir_all(dir)?;
+            let path = dir.join(format!("{}.{}", Uuid::new_v4().simple(), SOCKET_EXTENSION));
+            let receiver = UnixDatagram::bind(&path)?;
+            let sender = UnixDatagram::unbound()?;
+            // slow peers should not block the manager
+            sender.set_nonblocking(true)?;
+            info!("Unix bus socket bound;path={:?}", path);
+            Ok(Self {
+                path,
+                dir: dir.to_owned(),
+                receiver: Some(receiver),
+                sender,
+            })
+        }
+
+        fn peers(&self) -> Vec<PathBuf> {
+            fs::read_dir(&self.dir)
+                .map(|entries| {
+                    entries
+                        .filter_map(|entry| entry.ok().map(|e| e.path()))
+                        .filter(|path| {
+                            path != &self.path
+                                && path.extension().and_then(|e| e.to_str())
+                                    == Some(SOCKET_EXTENSION)
+                        })
+                        .collect()
+                })
+                .unwrap_or_default()
+        }
+    }
+
+    impl Broadcast for UnixBus {
+        fn publish(&self, envelope: Envelope) {
+            let data = serde_json::to_vec(&envelope).unwrap();
+            if data.len() > MAX_DATAGRAM {
+                warn!("bus_envelope_too_big;world={}", envelope.key);
+                return;
+            }
+            for peer in self.peers() {
+                match self.sender.send_to(&data, &peer) {
+                    Ok(_) => {}
+                    Err(err) if err.kind() == io::ErrorKind::ConnectionRefused => {
+                        // Instance is gone without cleaning up its socket
+                        debug!("Removing stale socket;path={:?}", peer);
+                        let _ = fs::remove_file(&peer);
+                    }
+                    Err(err) => warn!("bus_send_failed;path={:?},err={}", peer, err),
+                }
+            }
+        }
+
+        fn has_peers(&self) -> bool {
+            !self.peers().is_empty()
+        }
+
+        fn subscribe(&mut self, manager: Recipient<Envelope>) {
+            let socket = if let Some(socket) = self.receiver.take() {
+                socket
+            } else {
+                warn!("bus_already_subscribed;path={:?}", self.path);
+                return;
+            };
+            // blocking reads are done in a separate thread
+            thread::spawn(move || {
+                let mut buffer = vec![0; MAX_DATAGRAM];
+                loop {
+                    match socket.recv(&mut buffer) {
+                        Ok(size) => match serde_json::from_slice::<Envelope>(&buffer[..size]) {
+                            Ok(envelope) => manager.do_send(envelope),
+                            Err(err) => warn!("bus_invalid_envelope;err={}", err),
+                        },
+                        Err(err) => {
+                            warn!("bus_recv_failed;err={}", err);
+                            break;
+                        }
+                    }
+                }
+            });
+        }
+    }
+
+    impl Drop for UnixBus {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+}
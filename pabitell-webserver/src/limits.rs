@@ -0,0 +1,194 @@
+use anyhow::{anyhow, Error, Result};
+use serde::Deserialize;
+use std::{collections::HashMap, str::FromStr};
+
+/// Quotas which are applied within a namespace
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Limits {
+    /// Worlds with connected clients
+    pub max_worlds: usize,
+    /// Connected clients per world
+    pub max_clients: usize,
+    /// Size of a single message in bytes
+    pub max_message_size: usize,
+    /// Messages per second sent by a single client
+    pub max_message_rate: u32,
+    /// Events kept per world (older events are dropped)
+    pub max_events: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_worlds: 100,
+            max_clients: 32,
+            max_message_size: 64 * 1024,
+            max_message_rate: 20,
+            max_events: 1000,
+        }
+    }
+}
+
+impl Limits {
+    /// Zero limits would make the namespace unusable
+    pub fn validate(&self) -> Result<()> {
+        for (name, value) in [
+            ("max_worlds", self.max_worlds),
+            ("max_clients", self.max_clients),
+            ("max_message_size", self.max_message_size),
+            ("max_message_rate", self.max_message_rate as usize),
+            ("max_events", self.max_events),
+        ] {
+            if value == 0 {
+                return Err(anyhow!("Limit '{}' can't be zero", name));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Limits which differ from the defaults
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LimitsOverride {
+    pub max_worlds: Option<usize>,
+    pub max_clients: Option<usize>,
+    pub max_message_size: Option<usize>,
+    pub max_message_rate: Option<u32>,
+    pub max_events: Option<usize>,
+}
+
+impl LimitsOverride {
+    pub fn apply(&self, limits: Limits) -> Limits {
+        Limits {
+            max_worlds: self.max_worlds.unwrap_or(limits.max_worlds),
+            max_clients: self.max_clients.unwrap_or(limits.max_clients),
+            max_message_size: self.max_message_size.unwrap_or(limits.max_message_size),
+            max_message_rate: self.max_message_rate.unwrap_or(limits.max_message_rate),
+            max_events: self.max_events.unwrap_or(limits.max_events),
+        }
+    }
+}
+
+/// Parses `key=value` pairs separated by commas
+/// e.g. `max_worlds=10,max_clients=4`
+impl FromStr for LimitsOverride {
+    type Err = Error;
+
+    fn from_str(spec: &str) -> Result<Self> {
+        let mut res = Self::default();
+        for pair in spec.split(',').filter(|e| !e.is_empty()) {
+            let (key, value) = pair
+                .split_once('=')
+                .ok_or_else(|| anyhow!("Invalid limit '{}'", pair))?;
+            let value = value.trim();
+            match key.trim() {
+                "max_worlds" => res.max_worlds = Some(value.parse()?),
+                "max_clients" => res.max_clients = Some(value.parse()?),
+                "max_message_size" => res.max_message_size = Some(value.parse()?),
+                "max_message_rate" => res.max_message_rate = Some(value.parse()?),
+                "max_events" => res.max_events = Some(value.parse()?),
+                key => return Err(anyhow!("Unknown limit '{}'", key)),
+            }
+        }
+        Ok(res)
+    }
+}
+
+/// Default limits with overrides for particular namespaces
+/// and quotas of the whole server
+#[derive(Debug, Clone)]
+pub struct NamespaceLimits {
+    pub default: Limits,
+    pub namespaces: HashMap<String, Limits>,
+    /// Namespaces without overrides are refused
+    /// (otherwise any new namespace gets fresh default quotas)
+    pub strict: bool,
+    /// Worlds with connected clients in all namespaces
+    pub max_total_worlds: usize,
+    /// Connected clients in all namespaces
+    pub max_total_clients: usize,
+}
+
+impl Default for NamespaceLimits {
+    fn default() -> Self {
+        Self::new(Limits::default())
+    }
+}
+
+impl NamespaceLimits {
+    pub fn new(default: Limits) -> Self {
+        Self {
+            default,
+            namespaces: HashMap::new(),
+            strict: false,
+            max_total_worlds: 1000,
+            max_total_clients: 10_000,
+        }
+    }
+
+    pub fn add_override(&mut self, namespace: &str, limits: &LimitsOverride) {
+        self.namespaces
+            .insert(namespace.to_string(), limits.apply(self.default));
+    }
+
+    /// Limits of the namespace (`None` when the namespace is not allowed)
+    pub fn get(&self, namespace: &str) -> Option<Limits> {
+        match self.namespaces.get(namespace) {
+            Some(limits) => Some(*limits),
+            None if self.strict => None,
+            None => Some(self.default),
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::{Limits, LimitsOverride, NamespaceLimits};
+
+    #[test]
+    fn parse_overrides() {
+        let limits: LimitsOverride = " max_worlds = 10,max_events=5,".parse().unwrap();
+        assert_eq!(
+            limits,
+            LimitsOverride {
+                max_worlds: Some(10),
+                max_events: Some(5),
+                ..Default::default()
+            }
+        );
+        assert_eq!(
+            limits.apply(Limits::default()),
+            Limits {
+                max_worlds: 10,
+                max_events: 5,
+                ..Default::default()
+            }
+        );
+        assert_eq!("".parse::<LimitsOverride>().unwrap(), Default::default());
+
+        assert!("max_worlds".parse::<LimitsOverride>().is_err());
+        assert!("max_worlds=-1".parse::<LimitsOverride>().is_err());
+        assert!("max_worlds=many".parse::<LimitsOverride>().is_err());
+        assert!("max_planets=1".parse::<LimitsOverride>().is_err());
+    }
+
+    #[test]
+    fn namespaces() {
+        let mut limits = NamespaceLimits::default();
+        limits.add_override(
+            "kids",
+            &LimitsOverride {
+                max_clients: Some(4),
+                ..Default::default()
+            },
+        );
+        assert_eq!(limits.get("kids").unwrap().max_clients, 4);
+        assert_eq!(limits.get("other"), Some(Limits::default()));
+
+        limits.strict = true;
+        assert_eq!(limits.get("kids").unwrap().max_clients, 4);
+        assert_eq!(limits.get("other"), None);
+    }
+}
@@ -1,25 +1,208 @@
+pub mod api;
+pub mod broadcast;
+pub mod limits;
+pub mod metrics;
+pub mod settings;
+pub mod state;
+pub mod static_files;
+#[cfg(feature = "tls")]
+pub mod tls;
 pub mod web;
 pub mod websocket;
 
 use clap::{crate_authors, crate_description, crate_name, crate_version, App, Arg};
+use settings::Settings;
 
-pub fn main() {
-    let app = App::new(crate_name!())
+pub fn app() -> App<'static> {
+    App::new(crate_name!())
         .author(crate_authors!())
         .version(crate_version!())
         .about(crate_description!())
+        .arg(
+            Arg::new("config")
+                .help("Config file (TOML)")
+                .long("config")
+                .takes_value(true)
+                .required(false)
+                .env("PABITELL_CONFIG"),
+        )
         .arg(
             Arg::new("port")
                 .env("PORT")
-                .help("Port which will be used for the web server")
+                .help("Port which will be used for the web server (binds to 0.0.0.0)")
                 .long("port")
                 .takes_value(true)
                 .required(false)
-                .default_value("8080")
                 .env("PABITELL_WEB_PORT"),
-        );
+        )
+        .arg(
+            Arg::new("bind")
+                .help("Address to listen on (e.g. 0.0.0.0:8080 or [::]:8080), can be repeated")
+                .long("bind")
+                .takes_value(true)
+                .multiple_occurrences(true)
+                .use_value_delimiter(true)
+                .required(false)
+                .env("PABITELL_BIND"),
+        )
+        .arg(
+            Arg::new("workers")
+                .help("Number of http workers")
+                .long("workers")
+                .takes_value(true)
+                .required(false)
+                .env("PABITELL_WORKERS"),
+        )
+        .arg(
+            Arg::new("runtime-threads")
+                .help("Number of threads of the async runtime")
+                .long("runtime-threads")
+                .takes_value(true)
+                .required(false)
+                .env("PABITELL_RUNTIME_THREADS"),
+        )
+        .arg(
+            Arg::new("heartbeat-interval")
+                .help("How often heartbeat pings are sent (in seconds)")
+                .long("heartbeat-interval")
+                .takes_value(true)
+                .required(false)
+                .env("PABITELL_HEARTBEAT_INTERVAL"),
+        )
+        .arg(
+            Arg::new("client-timeout")
+                .help("How long before lack of client response causes a timeout (in seconds)")
+                .long("client-timeout")
+                .takes_value(true)
+                .required(false)
+                .env("PABITELL_CLIENT_TIMEOUT"),
+        )
+        .arg(
+            Arg::new("request-timeout")
+                .help("How long to wait for a response to a relayed request (in seconds)")
+                .long("request-timeout")
+                .takes_value(true)
+                .required(false)
+                .env("PABITELL_REQUEST_TIMEOUT"),
+        )
+        .arg(
+            Arg::new("shutdown-timeout")
+                .help("How long to wait for connections to close on shutdown (in seconds)")
+                .long("shutdown-timeout")
+                .takes_value(true)
+                .required(false)
+                .env("PABITELL_SHUTDOWN_TIMEOUT"),
+        )
+        .arg(
+            Arg::new("world-expiry")
+                .help("How long are worlds without clients kept (in seconds)")
+                .long("world-expiry")
+                .takes_value(true)
+                .required(false)
+                .env("PABITELL_WORLD_EXPIRY"),
+        )
+        .arg(
+            Arg::new("log-format")
+                .help("Format of the logs")
+                .long("log-format")
+                .takes_value(true)
+                .possible_values(["pretty", "compact", "json"])
+                .required(false)
+                .env("PABITELL_LOG_FORMAT"),
+        )
+        .arg(
+            Arg::new("tls-cert")
+                .help("PEM file with the certificate chain")
+                .long("tls-cert")
+                .takes_value(true)
+                .required(false)
+                .env("PABITELL_TLS_CERT"),
+        )
+        .arg(
+            Arg::new("tls-key")
+                .help("PEM file with the private key")
+                .long("tls-key")
+                .takes_value(true)
+                .required(false)
+                .env("PABITELL_TLS_KEY"),
+        )
+        .arg(
+            Arg::new("static-dir")
+                .help("Directory with compiled webapps ({namespace}/{story}/index.html)")
+                .long("static-dir")
+                .takes_value(true)
+                .required(false)
+                .env("PABITELL_STATIC_DIR"),
+        )
+        .arg(
+            Arg::new("state-file")
+                .help("File where secrets and events of the worlds are kept between restarts")
+                .long("state-file")
+                .takes_value(true)
+                .required(false)
+                .env("PABITELL_STATE_FILE"),
+        )
+        .arg(
+            Arg::new("bus-dir")
+                .help("Directory where instances sharing the worlds exchange messages")
+                .long("bus-dir")
+                .takes_value(true)
+                .required(false)
+                .env("PABITELL_BUS_DIR"),
+        )
+        .arg(
+            Arg::new("limits")
+                .help("Default limits of namespaces (e.g. max_worlds=100,max_clients=32,max_message_size=65536,max_message_rate=20,max_events=1000)")
+                .long("limits")
+                .takes_value(true)
+                .required(false)
+                .env("PABITELL_LIMITS"),
+        )
+        .arg(
+            Arg::new("namespace-limits")
+                .help("Limits of a particular namespace (e.g. kids:max_worlds=10,max_clients=4)")
+                .long("namespace-limits")
+                .takes_value(true)
+                .multiple_occurrences(true)
+                .required(false),
+        )
+        .arg(
+            Arg::new("strict-namespaces")
+                .help("Refuse namespaces which don't have their limits set")
+                .long("strict-namespaces")
+                .env("PABITELL_STRICT_NAMESPACES"),
+        )
+        .arg(
+            Arg::new("max-total-worlds")
+                .help("Worlds with connected clients in all namespaces")
+                .long("max-total-worlds")
+                .takes_value(true)
+                .required(false)
+                .env("PABITELL_MAX_TOTAL_WORLDS"),
+        )
+        .arg(
+            Arg::new("max-total-clients")
+                .help("Connected clients in all namespaces")
+                .long("max-total-clients")
+                .takes_value(true)
+                .required(false)
+                .env("PABITELL_MAX_TOTAL_CLIENTS"),
+        )
+        .arg(
+            Arg::new("admin-token")
+                .help("Token which grants access to the world listing (disabled when not set)")
+                .long("admin-token")
+                .takes_value(true)
+                .required(false)
+                .env("PABITELL_ADMIN_TOKEN"),
+        )
+}
 
-    let matches = app.clone().get_matches();
-    let port = matches.value_of("port").unwrap();
-    web::start_web_app(port).unwrap();
+pub fn main() {
+    let matches = app().get_matches();
+    let settings = Settings::load(&matches).unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        std::process::exit(1);
+    });
+    web::start_web_app(settings).unwrap();
 }
@@ -0,0 +1,139 @@
+use actix_web::{get, HttpResponse};
+use lazy_static::lazy_static;
+use pabitell_lib::protocol;
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+};
+
+lazy_static! {
+    pub static ref METRICS: Metrics = Metrics::default();
+}
+
+/// Counters which are exposed in prometheus text format
+#[derive(Debug, Default)]
+pub struct Metrics {
+    connected_clients: AtomicU64,
+    active_worlds: AtomicU64,
+    heartbeat_timeouts: AtomicU64,
+    /// Recieved messages by type
+    messages: Mutex<BTreeMap<&'static str, u64>>,
+    /// Closed connections by reason
+    disconnects: Mutex<BTreeMap<&'static str, u64>>,
+}
+
+impl Metrics {
+    pub fn set_connected_clients(&self, count: usize) {
+        self.connected_clients
+            .store(count as u64, Ordering::Relaxed);
+    }
+
+    pub fn set_active_worlds(&self, count: usize) {
+        self.active_worlds.store(count as u64, Ordering::Relaxed);
+    }
+
+    pub fn heartbeat_timeout(&self) {
+        self.heartbeat_timeouts.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn message(&self, kind: &'static str) {
+        *self.messages.lock().unwrap().entry(kind).or_default() += 1;
+    }
+
+    pub fn disconnect(&self, reason: &'static str) {
+        *self.disconnects.lock().unwrap().entry(reason).or_default() += 1;
+    }
+
+    /// Renders metrics in prometheus text exposition format
+    pub fn render(&self) -> String {
+        let mut res = String::new();
+        let mut metric = |name: &str, kind: &str, help: &str, values: Vec<(String, u64)>| {
+            writeln!(res, "# HELP {} {}", name, help).unwrap();
+            writeln!(res, "# TYPE {} {}", name, kind).unwrap();
+            for (labels, value) in values {
+                writeln!(res, "{}{} {}", name, labels, value).unwrap();
+            }
+        };
+
+        metric(
+            "pabitell_connected_clients",
+            "gauge",
+            "Clients connected via websocket",
+            vec![(
+                String::new(),
+                self.connected_clients.load(Ordering::Relaxed),
+            )],
+        );
+        metric(
+            "pabitell_active_worlds",
+            "gauge",
+            "Worlds with connected clients",
+            vec![(String::new(), self.active_worlds.load(Ordering::Relaxed))],
+        );
+        metric(
+            "pabitell_heartbeat_timeouts_total",
+            "counter",
+            "Clients disconnected due to missing heartbeat",
+            vec![(
+                String::new(),
+                self.heartbeat_timeouts.load(Ordering::Relaxed),
+            )],
+        );
+        metric(
+            "pabitell_messages_total",
+            "counter",
+            "Messages recieved from clients by type",
+            labeled("type", &self.messages.lock().unwrap()),
+        );
+        metric(
+            "pabitell_disconnects_total",
+            "counter",
+            "Closed websocket connections by reason",
+            labeled("reason", &self.disconnects.lock().unwrap()),
+        );
+        res
+    }
+}
+
+fn labeled(label: &str, values: &BTreeMap<&'static str, u64>) -> Vec<(String, u64)> {
+    values
+        .iter()
+        .map(|(key, value)| (format!("{{{}=\"{}\"}}", label, key), *value))
+        .collect()
+}
+
+/// Label of the message used in metrics
+pub fn message_kind(message: &protocol::Message) -> &'static str {
+    match message {
+        protocol::Message::Notification(notification) => match notification {
+            protocol::NotificationMessage::Event(_) => "notification_event",
+            protocol::NotificationMessage::Joined(_) => "notification_joined",
+            protocol::NotificationMessage::WorldUpdate => "notification_world_update",
+            protocol::NotificationMessage::Elected(_) => "notification_elected",
+        },
+        protocol::Message::Request(request) => match request {
+            protocol::RequestMessage::GetWorld(_) => "request_get_world",
+            protocol::RequestMessage::TriggerEvent(_) => "request_trigger_event",
+            protocol::RequestMessage::ClaimOwnership(_) => "request_claim_ownership",
+            protocol::RequestMessage::TransferOwnership(_) => "request_transfer_ownership",
+        },
+        protocol::Message::Response(response) => match response {
+            protocol::ResponseMessage::GetWorld(_) => "response_get_world",
+            protocol::ResponseMessage::TriggerEvent(_) => "response_trigger_event",
+            protocol::ResponseMessage::ClaimOwnership(_) => "response_claim_ownership",
+            protocol::ResponseMessage::TransferOwnership(_) => "response_transfer_ownership",
+            protocol::ResponseMessage::Error(_) => "response_error",
+        },
+    }
+}
+
+#[get("/metrics")]
+pub async fn metrics() -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(METRICS.render())
+}
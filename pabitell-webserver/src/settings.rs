@@ -0,0 +1,368 @@
+use anyhow::{anyhow, Result};
+use clap::ArgMatches;
+use serde::Deserialize;
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
+
+use crate::{
+    limits::{Limits, LimitsOverride, NamespaceLimits},
+    websocket::Timeouts,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LogFormat {
+    Pretty,
+    Compact,
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        match value {
+            "pretty" => Ok(Self::Pretty),
+            "compact" => Ok(Self::Compact),
+            "json" => Ok(Self::Json),
+            _ => Err(anyhow!("Unknown log format '{}'", value)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TlsSettings {
+    /// PEM file with the certificate chain
+    pub cert: PathBuf,
+    /// PEM file with the private key
+    pub key: PathBuf,
+}
+
+/// Settings of the webserver
+/// values from the config file can be overriden by CLI arguments or env variables
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Settings {
+    /// Addresses to listen on (e.g. `0.0.0.0:8080` or `[::]:8080`)
+    pub bind: Vec<String>,
+    /// Number of http workers
+    pub workers: usize,
+    /// Number of threads of the async runtime
+    pub runtime_threads: usize,
+    /// How often heartbeat pings are sent (in seconds)
+    pub heartbeat_interval: u64,
+    /// How long before lack of client response causes a timeout (in seconds)
+    pub client_timeout: u64,
+    /// How long to wait for a response to a relayed request (in seconds)
+    pub request_timeout: u64,
+    /// How long to wait for connections to close on shutdown (in seconds)
+    pub shutdown_timeout: u64,
+    /// How long are worlds without clients kept (in seconds)
+    pub world_expiry: u64,
+    pub log_format: LogFormat,
+    pub tls: Option<TlsSettings>,
+    /// Directory with compiled webapps
+    pub static_dir: Option<PathBuf>,
+    /// File where secrets and events of the worlds are kept between restarts
+    pub state_file: Option<PathBuf>,
+    /// Directory of the unix bus which is shared by multiple server instances
+    pub bus_dir: Option<PathBuf>,
+    /// Default limits of namespaces
+    pub limits: Limits,
+    /// Limits of particular namespaces
+    pub namespaces: HashMap<String, LimitsOverride>,
+    /// Only namespaces from `namespaces` can be used
+    pub strict_namespaces: bool,
+    /// Worlds with connected clients in all namespaces
+    pub max_total_worlds: usize,
+    /// Connected clients in all namespaces
+    pub max_total_clients: usize,
+    /// Token of the admin endpoints (e.g. listing of the worlds)
+    pub admin_token: Option<String>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            bind: vec!["0.0.0.0:8080".to_string()],
+            workers: 8,
+            runtime_threads: 8,
+            heartbeat_interval: 5,
+            client_timeout: 10,
+            request_timeout: 5,
+            shutdown_timeout: 10,
+            world_expiry: 7 * 24 * 60 * 60,
+            log_format: LogFormat::Pretty,
+            tls: None,
+            static_dir: None,
+            state_file: None,
+            bus_dir: None,
+            limits: Limits::default(),
+            namespaces: HashMap::new(),
+            strict_namespaces: false,
+            max_total_worlds: 1000,
+            max_total_clients: 10_000,
+            admin_token: None,
+        }
+    }
+}
+
+fn parse<T>(matches: &ArgMatches, name: &str) -> Result<Option<T>>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    matches
+        .value_of(name)
+        .map(|value| {
+            value
+                .parse()
+                .map_err(|err| anyhow!("Invalid value of '{}': {}", name, err))
+        })
+        .transpose()
+}
+
+impl Settings {
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path)
+            .map_err(|err| anyhow!("Failed to read config {:?}: {}", path, err))?;
+        // values are validated after the CLI overrides are applied
+        toml::from_str(&content).map_err(|err| anyhow!("Invalid config {:?}: {}", path, err))
+    }
+
+    /// Checks values which would make the server unusable or panic
+    pub fn validate(&self) -> Result<()> {
+        if self.bind.is_empty() {
+            return Err(anyhow!("No address to bind to"));
+        }
+        for (name, value) in [
+            ("workers", self.workers as u64),
+            ("runtime_threads", self.runtime_threads as u64),
+            ("heartbeat_interval", self.heartbeat_interval),
+            ("client_timeout", self.client_timeout),
+            ("request_timeout", self.request_timeout),
+            ("world_expiry", self.world_expiry),
+            ("max_total_worlds", self.max_total_worlds as u64),
+            ("max_total_clients", self.max_total_clients as u64),
+        ] {
+            if value == 0 {
+                return Err(anyhow!("'{}' can't be zero", name));
+            }
+        }
+        if self.client_timeout <= self.heartbeat_interval {
+            return Err(anyhow!(
+                "'client_timeout' has to be longer than 'heartbeat_interval'"
+            ));
+        }
+        if self.admin_token.as_deref() == Some("") {
+            return Err(anyhow!("'admin_token' can't be empty"));
+        }
+        self.limits.validate()?;
+        for (namespace, limits) in &self.namespaces {
+            limits
+                .apply(self.limits)
+                .validate()
+                .map_err(|err| anyhow!("Namespace '{}': {}", namespace, err))?;
+        }
+        Ok(())
+    }
+
+    /// Reads the config file and applies overrides from CLI arguments
+    pub fn load(matches: &ArgMatches) -> Result<Self> {
+        let mut settings = if let Some(path) = matches.value_of("config") {
+            Self::from_file(Path::new(path))?
+        } else {
+            Self::default()
+        };
+
+        if let Some(bind) = matches.values_of("bind") {
+            settings.bind = bind.map(String::from).collect();
+        } else if let Some(port) = parse::<u16>(matches, "port")? {
+            settings.bind = vec![format!("0.0.0.0:{}", port)];
+        }
+        if let Some(workers) = parse(matches, "workers")? {
+            settings.workers = workers;
+        }
+        if let Some(threads) = parse(matches, "runtime-threads")? {
+            settings.runtime_threads = threads;
+        }
+        if let Some(interval) = parse(matches, "heartbeat-interval")? {
+            settings.heartbeat_interval = interval;
+        }
+        if let Some(timeout) = parse(matches, "client-timeout")? {
+            settings.client_timeout = timeout;
+        }
+        if let Some(timeout) = parse(matches, "request-timeout")? {
+            settings.request_timeout = timeout;
+        }
+        if let Some(timeout) = parse(matches, "shutdown-timeout")? {
+            settings.shutdown_timeout = timeout;
+        }
+        if let Some(expiry) = parse(matches, "world-expiry")? {
+            settings.world_expiry = expiry;
+        }
+        if let Some(format) = parse(matches, "log-format")? {
+            settings.log_format = format;
+        }
+        match (matches.value_of("tls-cert"), matches.value_of("tls-key")) {
+            (Some(cert), Some(key)) => {
+                settings.tls = Some(TlsSettings {
+                    cert: cert.into(),
+                    key: key.into(),
+                })
+            }
+            (None, None) => {}
+            _ => return Err(anyhow!("Both 'tls-cert' and 'tls-key' need to be set")),
+        }
+        if let Some(static_dir) = matches.value_of("static-dir") {
+            settings.static_dir = Some(static_dir.into());
+        }
+        if let Some(state_file) = matches.value_of("state-file") {
+            settings.state_file = Some(state_file.into());
+        }
+        if let Some(bus_dir) = matches.value_of("bus-dir") {
+            settings.bus_dir = Some(bus_dir.into());
+        }
+        if let Some(limits) = parse::<LimitsOverride>(matches, "limits")? {
+            settings.limits = limits.apply(settings.limits);
+        }
+        for spec in matches.values_of("namespace-limits").into_iter().flatten() {
+            let (namespace, limits) = spec
+                .split_once(':')
+                .ok_or_else(|| anyhow!("Missing namespace in '{}'", spec))?;
+            settings
+                .namespaces
+                .insert(namespace.to_string(), limits.parse()?);
+        }
+        if matches.is_present("strict-namespaces") {
+            settings.strict_namespaces = true;
+        }
+        if let Some(max) = parse(matches, "max-total-worlds")? {
+            settings.max_total_worlds = max;
+        }
+        if let Some(max) = parse(matches, "max-total-clients")? {
+            settings.max_total_clients = max;
+        }
+        if let Some(token) = matches.value_of("admin-token") {
+            settings.admin_token = Some(token.to_string());
+        }
+
+        settings.validate()?;
+        Ok(settings)
+    }
+
+    pub fn namespace_limits(&self) -> NamespaceLimits {
+        let mut res = NamespaceLimits::new(self.limits);
+        for (namespace, limits) in &self.namespaces {
+            res.add_override(namespace, limits);
+        }
+        res.strict = self.strict_namespaces;
+        res.max_total_worlds = self.max_total_worlds;
+        res.max_total_clients = self.max_total_clients;
+        res
+    }
+
+    pub fn timeouts(&self) -> Timeouts {
+        Timeouts {
+            heartbeat_interval: Duration::from_secs(self.heartbeat_interval),
+            client_timeout: Duration::from_secs(self.client_timeout),
+            request_timeout: Duration::from_secs(self.request_timeout),
+            world_expiry: Duration::from_secs(self.world_expiry),
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::{LogFormat, Settings};
+    use std::{env, fs};
+
+    fn load(args: &[&str]) -> anyhow::Result<Settings> {
+        let matches = crate::app()
+            .try_get_matches_from(["pabitell-webserver"].iter().chain(args.iter()).copied())?;
+        Settings::load(&matches)
+    }
+
+    #[test]
+    fn load_settings() {
+        let settings = load(&[]).unwrap();
+        assert_eq!(settings.bind, vec!["0.0.0.0:8080"]);
+
+        let path = env::temp_dir().join(format!("pabitell-settings-{}.toml", std::process::id()));
+        fs::write(
+            &path,
+            r#"
+bind = ["127.0.0.1:8000"]
+workers = 2
+log_format = "json"
+
+[limits]
+max_worlds = 5
+
+[namespaces.kids]
+max_clients = 4
+"#,
+        )
+        .unwrap();
+        let config = path.to_str().unwrap();
+
+        let settings = load(&["--config", config]).unwrap();
+        assert_eq!(settings.bind, vec!["127.0.0.1:8000"]);
+        assert_eq!(settings.workers, 2);
+        assert_eq!(settings.log_format, LogFormat::Json);
+        let limits = settings.namespace_limits();
+        assert_eq!(limits.get("kids").unwrap().max_worlds, 5);
+        assert_eq!(limits.get("kids").unwrap().max_clients, 4);
+
+        // CLI arguments take precedence
+        let settings = load(&[
+            "--config",
+            config,
+            "--port",
+            "9000",
+            "--workers",
+            "3",
+            "--limits",
+            "max_worlds=7",
+        ])
+        .unwrap();
+        assert_eq!(settings.bind, vec!["0.0.0.0:9000"]);
+        assert_eq!(settings.workers, 3);
+        assert_eq!(settings.limits.max_worlds, 7);
+
+        let settings = load(&["--config", config, "--bind", "[::]:80,127.0.0.1:80"]).unwrap();
+        assert_eq!(settings.bind, vec!["[::]:80", "127.0.0.1:80"]);
+
+        fs::write(&path, "heartbeat_interval = 0").unwrap();
+        assert!(load(&["--config", config]).is_err());
+        // CLI arguments can fix the values from the file
+        fs::write(&path, "client_timeout = 5\nheartbeat_interval = 5").unwrap();
+        assert!(load(&["--config", config]).is_err());
+        let settings = load(&["--config", config, "--client-timeout", "15"]).unwrap();
+        assert_eq!(settings.client_timeout, 15);
+        fs::write(&path, "workers = \"many\"").unwrap();
+        assert!(load(&["--config", config]).is_err());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn validate_settings() {
+        assert!(Settings::default().validate().is_ok());
+
+        assert!(load(&["--heartbeat-interval", "0"]).is_err());
+        assert!(load(&["--request-timeout", "0"]).is_err());
+        assert!(load(&["--workers", "0"]).is_err());
+        assert!(load(&["--max-total-clients", "0"]).is_err());
+        assert!(load(&["--heartbeat-interval", "20"]).is_err());
+        assert!(load(&["--limits", "max_message_rate=0"]).is_err());
+        assert!(load(&["--namespace-limits", "kids:max_worlds=0"]).is_err());
+        assert!(load(&["--tls-cert", "cert.pem"]).is_err());
+    }
+}
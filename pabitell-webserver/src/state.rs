@@ -0,0 +1,39 @@
+use anyhow::Result;
+use pabitell_lib::protocol;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    io::{BufReader, BufWriter, Write},
+    path::Path,
+};
+use uuid::Uuid;
+
+/// World data which should survive restarts of the server
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StoredWorld {
+    pub namespace: String,
+    pub story: String,
+    pub world_id: Uuid,
+    pub secrets: Option<protocol::JoinSecrets>,
+    pub events: Vec<protocol::EventNotification>,
+}
+
+/// Loads stored worlds (missing file means that nothing was stored yet)
+pub fn load(path: &Path) -> Result<Vec<StoredWorld>> {
+    if !path.exists() {
+        return Ok(vec![]);
+    }
+    let reader = BufReader::new(fs::File::open(path)?);
+    Ok(serde_json::from_reader(reader)?)
+}
+
+/// Stores worlds (temporary file is used so the old state is kept on failure)
+pub fn save(path: &Path, worlds: &[StoredWorld]) -> Result<()> {
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(".tmp");
+    let mut writer = BufWriter::new(fs::File::create(&tmp_path)?);
+    serde_json::to_writer(&mut writer, worlds)?;
+    writer.flush()?;
+    fs::rename(&tmp_path, path)?;
+    Ok(())
+}
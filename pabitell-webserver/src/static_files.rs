@@ -0,0 +1,139 @@
+use actix_files::{file_extension_to_mime, NamedFile};
+use actix_web::{
+    error, get,
+    http::header::{self, ContentEncoding, HeaderValue},
+    web, Error, HttpRequest, HttpResponse,
+};
+use std::path::{Path, PathBuf};
+use tracing::debug;
+
+/// How long can browsers cache static assets (in seconds)
+const MAX_AGE: u32 = 24 * 60 * 60;
+/// Entry point of the webapp
+const INDEX: &str = "index.html";
+
+/// Directory which contains `{namespace}/{story}/` subdirectories
+/// with compiled webapps
+#[derive(Debug, Clone)]
+pub struct StaticRoot(pub PathBuf);
+
+/// Converts url path to relative fs path (hidden files and `..` are refused)
+fn sanitize(path: &str) -> Option<PathBuf> {
+    let mut res = PathBuf::new();
+    for part in path.split('/').filter(|e| !e.is_empty()) {
+        if part.starts_with('.') || part.contains('\\') {
+            return None;
+        }
+        res.push(part);
+    }
+    Some(res)
+}
+
+/// Precompressed variants which are accepted by the client (preferred first)
+fn accepted_encodings(req: &HttpRequest) -> Vec<(ContentEncoding, &'static str)> {
+    let accepted: Vec<&str> = req
+        .headers()
+        .get(header::ACCEPT_ENCODING)
+        .and_then(|e| e.to_str().ok())
+        .unwrap_or_default()
+        .split(',')
+        .filter_map(|e| e.split(';').next())
+        .map(|e| e.trim())
+        .collect();
+    [
+        (ContentEncoding::Brotli, "br"),
+        (ContentEncoding::Gzip, "gz"),
+    ]
+    .into_iter()
+    .filter(|(encoding, _)| accepted.contains(&encoding.as_str()))
+    .collect()
+}
+
+/// Opens the file or its precompressed variant
+fn open(req: &HttpRequest, path: &Path) -> Option<NamedFile> {
+    if !path.is_file() {
+        return None;
+    }
+
+    // mime is derived from the original name (`.wasm` -> `application/wasm`)
+    let mime = file_extension_to_mime(
+        path.extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or_default(),
+    );
+    for (encoding, suffix) in accepted_encodings(req) {
+        let mut compressed = path.as_os_str().to_owned();
+        compressed.push(".");
+        compressed.push(suffix);
+        if let Ok(file) = NamedFile::open(&compressed) {
+            debug!("static_precompressed;path={:?}", compressed);
+            return Some(
+                file.set_content_type(mime)
+                    .set_content_encoding(encoding)
+                    .disable_content_disposition(),
+            );
+        }
+    }
+    NamedFile::open(path)
+        .ok()
+        .map(|file| file.set_content_type(mime).disable_content_disposition())
+}
+
+/// Serves files of the story webapp
+/// unknown paths fall back to `index.html` so the webapp can handle them
+#[get("/{namespace}/{story}/{tail:.*}")]
+pub async fn static_files(
+    req: HttpRequest,
+    root: web::Data<StaticRoot>,
+    path: web::Path<(String, String, String)>,
+) -> Result<HttpResponse, Error> {
+    let (namespace, story, tail) = path.into_inner();
+    let story_dir = sanitize(&format!("{}/{}", namespace, story))
+        .map(|e| root.0.join(e))
+        .filter(|e| e.is_dir())
+        .ok_or_else(|| error::ErrorNotFound("story not found"))?;
+    let file_path = sanitize(&tail).ok_or_else(|| error::ErrorNotFound("file not found"))?;
+
+    let (file, is_index) = if let Some(file) = open(&req, &story_dir.join(&file_path)) {
+        (file, file_path.file_name() == Some(INDEX.as_ref()))
+    } else if let Some(file) = open(&req, &story_dir.join(INDEX)) {
+        (file, true)
+    } else {
+        return Err(error::ErrorNotFound("file not found"));
+    };
+
+    let mut response = file.into_response(&req);
+    let cache_control = if is_index {
+        // index refers to assets of the current build
+        HeaderValue::from_static("no-cache")
+    } else {
+        HeaderValue::from_str(&format!("public, max-age={}", MAX_AGE)).unwrap()
+    };
+    let headers = response.headers_mut();
+    headers.insert(header::CACHE_CONTROL, cache_control);
+    headers.insert(header::VARY, HeaderValue::from_static("accept-encoding"));
+    Ok(response)
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::sanitize;
+    use std::path::PathBuf;
+
+    #[test]
+    fn sanitize_path() {
+        assert_eq!(
+            sanitize("/assets//app.wasm"),
+            Some(PathBuf::from("assets/app.wasm"))
+        );
+        assert_eq!(sanitize(""), Some(PathBuf::new()));
+        // path traversal
+        assert_eq!(sanitize("../secret"), None);
+        assert_eq!(sanitize("assets/../../secret"), None);
+        assert_eq!(sanitize("..\\secret"), None);
+        assert_eq!(sanitize("assets\\..\\secret"), None);
+        // hidden files
+        assert_eq!(sanitize(".env"), None);
+        assert_eq!(sanitize("assets/.git/config"), None);
+    }
+}
@@ -0,0 +1,114 @@
+use anyhow::{anyhow, Result};
+use rustls::{
+    server::{ClientHello, ResolvesServerCert},
+    sign::{self, CertifiedKey},
+    Certificate, PrivateKey, ServerConfig,
+};
+use std::{
+    fs,
+    io::BufReader,
+    path::Path,
+    sync::{Arc, Mutex, RwLock},
+    time::{Duration, SystemTime},
+};
+use tracing::{error, info};
+
+use crate::settings::TlsSettings;
+
+/// How often are the certificate files checked for changes
+pub const RELOAD_INTERVAL: Duration = Duration::from_secs(10);
+
+fn load_certs(path: &Path) -> Result<Vec<Certificate>> {
+    let mut reader = BufReader::new(fs::File::open(path)?);
+    let certs: Vec<Certificate> = rustls_pemfile::certs(&mut reader)?
+        .into_iter()
+        .map(Certificate)
+        .collect();
+    if certs.is_empty() {
+        return Err(anyhow!("No certificate found in {:?}", path));
+    }
+    Ok(certs)
+}
+
+fn load_key(path: &Path) -> Result<PrivateKey> {
+    let mut reader = BufReader::new(fs::File::open(path)?);
+    while let Some(item) = rustls_pemfile::read_one(&mut reader)? {
+        match item {
+            rustls_pemfile::Item::RSAKey(key)
+            | rustls_pemfile::Item::PKCS8Key(key)
+            | rustls_pemfile::Item::ECKey(key) => return Ok(PrivateKey(key)),
+            _ => {}
+        }
+    }
+    Err(anyhow!("No private key found in {:?}", path))
+}
+
+fn load_certified_key(settings: &TlsSettings) -> Result<CertifiedKey> {
+    let certs = load_certs(&settings.cert)?;
+    let key = sign::any_supported_type(&load_key(&settings.key)?)
+        .map_err(|_| anyhow!("Unsupported private key in {:?}", settings.key))?;
+    Ok(CertifiedKey::new(certs, key))
+}
+
+/// Last modification of the certificate files
+fn modified(settings: &TlsSettings) -> Option<SystemTime> {
+    let cert = fs::metadata(&settings.cert)
+        .and_then(|e| e.modified())
+        .ok()?;
+    let key = fs::metadata(&settings.key)
+        .and_then(|e| e.modified())
+        .ok()?;
+    Some(cert.max(key))
+}
+
+/// Provides the current certificate and reloads it when its files change
+pub struct CertResolver {
+    settings: TlsSettings,
+    key: RwLock<Arc<CertifiedKey>>,
+    modified: Mutex<Option<SystemTime>>,
+}
+
+impl CertResolver {
+    pub fn new(settings: TlsSettings) -> Result<Arc<Self>> {
+        let modified = modified(&settings);
+        let key = load_certified_key(&settings)?;
+        info!("Certificate loaded;cert={:?}", settings.cert);
+        Ok(Arc::new(Self {
+            settings,
+            key: RwLock::new(Arc::new(key)),
+            modified: Mutex::new(modified),
+        }))
+    }
+
+    /// Reloads the certificate when one of the files was changed
+    /// the old certificate is kept when the new one can't be loaded
+    pub fn reload_if_changed(&self) {
+        let current = modified(&self.settings);
+        let mut modified = self.modified.lock().unwrap();
+        if current.is_none() || current == *modified {
+            return;
+        }
+        match load_certified_key(&self.settings) {
+            Ok(key) => {
+                info!("Certificate reloaded;cert={:?}", self.settings.cert);
+                *self.key.write().unwrap() = Arc::new(key);
+                *modified = current;
+            }
+            // files might be in the middle of an update, try it again later
+            Err(err) => error!("Failed to reload certificate: {}", err),
+        }
+    }
+
+    pub fn server_config(self: &Arc<Self>) -> ServerConfig {
+        ServerConfig::builder()
+            .with_safe_defaults()
+            .with_no_client_auth()
+            .with_cert_resolver(self.clone())
+    }
+}
+
+impl ResolvesServerCert for CertResolver {
+    fn resolve(&self, _: ClientHello) -> Option<Arc<CertifiedKey>> {
+        Some(self.key.read().unwrap().clone())
+    }
+}
@@ -1,64 +1,236 @@
 use actix::*;
-use actix_web::{get, web, App, Error, HttpRequest, HttpResponse, HttpServer};
+use actix_web::{
+    error, get, http::header::SEC_WEBSOCKET_PROTOCOL, web, App, Error, HttpRequest, HttpResponse,
+    HttpServer,
+};
 use actix_web_actors::ws;
-use std::time::Instant;
-use tracing::info;
+use pabitell_lib::protocol;
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
 use tracing_actix_web::TracingLogger;
 use tracing_subscriber::{self, EnvFilter};
 use uuid::Uuid;
 
-use crate::websocket::{WsConnection, WsManager};
+use crate::{
+    api,
+    broadcast::{Broadcast, MemoryBroadcast, Peers},
+    limits::NamespaceLimits,
+    metrics,
+    settings::{LogFormat, Settings},
+    static_files::{self, StaticRoot},
+    websocket::{Access, Authorize, Shutdown, Timeouts, WorldKey, WsConnection, WsManager},
+};
 
-const WORKERS: usize = 8;
+#[cfg(unix)]
+use crate::broadcast::UnixBus;
+#[cfg(feature = "tls")]
+use crate::tls;
 
 #[get("/ws/{namespace}/{story}/{world}/")]
 async fn ws_endpoint(
     req: HttpRequest,
     data: web::Data<Addr<WsManager>>,
+    limits: web::Data<NamespaceLimits>,
+    timeouts: web::Data<Timeouts>,
     path: web::Path<(String, String, Uuid)>,
     stream: web::Payload,
 ) -> std::result::Result<HttpResponse, Error> {
-    ws::start(
-        WsConnection::new(Instant::now(), path.2, data.get_ref().clone()),
+    let (namespace, story, world_id) = path.into_inner();
+    // Browsers can't set custom headers of websocket requests
+    // so the join secret is passed as a subprotocol
+    let secret = req
+        .headers()
+        .get(SEC_WEBSOCKET_PROTOCOL)
+        .and_then(|e| e.to_str().ok())
+        .and_then(protocol::ws_secret);
+    let key = WorldKey {
+        namespace,
+        story,
+        world_id,
+    };
+    let frame_size = if let Some(limits) = limits.get(&key.namespace) {
+        limits.max_message_size
+    } else {
+        warn!("unknown_namespace;world={}", key);
+        return Ok(HttpResponse::NotFound().finish());
+    };
+    let access = data
+        .send(Authorize {
+            key: key.clone(),
+            secret: secret.clone(),
+        })
+        .await
+        .map_err(error::ErrorInternalServerError)?;
+    if access == Access::Denied {
+        warn!("access_denied;world={}", key);
+        return Ok(HttpResponse::Forbidden().finish());
+    }
+
+    ws::WsResponseBuilder::new(
+        WsConnection::new(
+            Instant::now(),
+            key,
+            secret,
+            *timeouts.get_ref(),
+            data.get_ref().clone(),
+        ),
         &req,
         stream,
     )
+    .frame_size(frame_size)
+    .protocols(&[protocol::WS_PROTOCOL])
+    .start()
+}
+
+/// Resolves when SIGINT or SIGTERM is recieved
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use actix_web::rt::signal::unix::{signal, SignalKind};
+        let mut terminate = signal(SignalKind::terminate()).expect("failed to listen to SIGTERM");
+        let ctrl_c = Box::pin(actix_web::rt::signal::ctrl_c());
+        let terminate = Box::pin(terminate.recv());
+        futures::future::select(ctrl_c, terminate).await;
+    }
+    #[cfg(not(unix))]
+    let _ = actix_web::rt::signal::ctrl_c().await;
 }
 
-async fn start(port: &str) -> anyhow::Result<()> {
+async fn start(settings: Settings) -> anyhow::Result<()> {
     // setting logging collector
-    let _collector = tracing_subscriber::fmt()
-        .with_env_filter(
-            EnvFilter::try_from_env("PABITELL_LOG_LEVEL").unwrap_or_else(|_| "info".into()),
-        )
-        .pretty()
-        .try_init();
+    let filter = EnvFilter::try_from_env("PABITELL_LOG_LEVEL").unwrap_or_else(|_| "info".into());
+    let collector = tracing_subscriber::fmt().with_env_filter(filter);
+    let _ = match settings.log_format {
+        LogFormat::Pretty => collector.pretty().try_init(),
+        LogFormat::Compact => collector.compact().try_init(),
+        LogFormat::Json => collector.json().try_init(),
+    };
     info!("Logging");
 
+    let limits = settings.namespace_limits();
+    let timeouts = settings.timeouts();
+    let shutdown_timeout = Duration::from_secs(settings.shutdown_timeout);
+    let static_dir = settings.static_dir.clone();
+    let admin_token = api::AdminToken(settings.admin_token.clone());
+    #[cfg(not(feature = "tls"))]
+    if settings.tls.is_some() {
+        return Err(anyhow::anyhow!("Server was built without TLS support"));
+    }
+    #[cfg(feature = "tls")]
+    let tls_config = if let Some(tls) = settings.tls.clone() {
+        let resolver = tls::CertResolver::new(tls)?;
+        let config = resolver.server_config();
+        // Certificates are reloaded when renewed
+        actix_web::rt::spawn(async move {
+            let mut interval = actix_web::rt::time::interval(tls::RELOAD_INTERVAL);
+            loop {
+                interval.tick().await;
+                resolver.reload_if_changed();
+            }
+        });
+        Some(config)
+    } else {
+        None
+    };
+
+    let broadcast: Box<dyn Broadcast> = match settings.bus_dir.as_ref() {
+        #[cfg(unix)]
+        Some(bus_dir) => Box::new(UnixBus::new(bus_dir)?),
+        #[cfg(not(unix))]
+        Some(_) => {
+            return Err(anyhow::anyhow!(
+                "Unix bus is not supported on this platform"
+            ))
+        }
+        None => Box::new(MemoryBroadcast::new()),
+    };
+
     // Start chat server actor
-    let ws_manager = WsManager::new().start();
+    let mut ws_manager = WsManager::new(limits.clone(), timeouts, Peers::new(broadcast));
+    if let Some(state_file) = settings.state_file.clone() {
+        ws_manager = ws_manager.with_state_file(state_file)?;
+    }
+    let ws_manager = ws_manager.start();
+    let manager = ws_manager.clone();
+
+    if let Some(static_dir) = static_dir.as_ref() {
+        info!("Serving static files from {:?}", static_dir);
+    }
 
-    HttpServer::new(move || {
-        App::new()
+    let mut server = HttpServer::new(move || {
+        let app = App::new()
             .app_data(web::Data::new(ws_manager.to_owned()))
+            .app_data(web::Data::new(limits.to_owned()))
+            .app_data(web::Data::new(timeouts))
+            .app_data(web::Data::new(admin_token.to_owned()))
             .wrap(TracingLogger::default())
             .service(ws_endpoint)
+            .service(api::list_worlds)
+            .service(api::get_world)
+            .service(api::get_events)
+            .service(api::post_event)
+            .service(metrics::metrics);
+
+        // Needs to be registered last, because it matches almost everything
+        if let Some(static_dir) = static_dir.as_ref() {
+            app.app_data(web::Data::new(StaticRoot(static_dir.to_owned())))
+                .service(static_files::static_files)
+        } else {
+            app
+        }
     })
-    .workers(WORKERS)
-    .bind(format!("0.0.0.0:{}", port))?
-    .run()
-    .await
-    .map_err(|e| e.into())
+    .workers(settings.workers)
+    .shutdown_timeout(shutdown_timeout.as_secs())
+    // signals are handled bellow to close websockets properly
+    .disable_signals();
+
+    for addr in &settings.bind {
+        #[cfg(feature = "tls")]
+        if let Some(config) = tls_config.as_ref() {
+            info!("Listening on https://{}", addr);
+            server = server.bind_rustls(addr, config.clone())?;
+            continue;
+        }
+        info!("Listening on http://{}", addr);
+        server = server.bind(addr)?;
+    }
+    let server = server.run();
+
+    let handle = server.handle();
+    actix_web::rt::spawn(async move {
+        shutdown_signal().await;
+        info!("Graceful shutdown started");
+
+        // Make sure that the server exits within the deadline
+        let system = actix_web::rt::System::current();
+        actix_web::rt::spawn(async move {
+            actix_web::rt::time::sleep(shutdown_timeout).await;
+            warn!("Shutdown deadline exceeded");
+            system.stop();
+        });
+
+        // Stop accepting new connections
+        handle.pause().await;
+        // Close websockets and store the state
+        if let Err(err) = manager.send(Shutdown).await {
+            warn!("Failed to shutdown manager: {}", err);
+        }
+        // Wait for remaining connections
+        handle.stop(true).await;
+    });
+
+    server.await.map_err(|e| e.into())
 }
 
-pub fn start_web_app(port: &str) -> anyhow::Result<()> {
-    actix_web::rt::System::with_tokio_rt(|| {
+pub fn start_web_app(settings: Settings) -> anyhow::Result<()> {
+    let threads = settings.runtime_threads;
+    actix_web::rt::System::with_tokio_rt(move || {
         tokio::runtime::Builder::new_multi_thread()
             .enable_all()
-            .worker_threads(8)
+            .worker_threads(threads)
             .thread_name("main-tokio")
             .build()
             .unwrap()
     })
-    .block_on(start(port))
+    .block_on(start(settings))
 }
@@ -1,37 +1,168 @@
 use actix::prelude::*;
 use actix_web_actors::ws;
+use pabitell_lib::protocol;
 use rand::{self, rngs::ThreadRng, Rng};
+use serde::{Deserialize, Serialize};
 use std::{
     collections::HashMap,
+    fmt,
+    path::PathBuf,
     time::{Duration, Instant},
 };
-use tracing::{debug, info};
+use tokio::sync::oneshot;
+use tracing::{debug, error, info, warn};
 use uuid::Uuid;
 
-/// How often heartbeat pings are sent
-const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
-/// How long before lack of client response causes a timeout
-const CLIENT_TIMEOUT: Duration = Duration::from_secs(10);
+use crate::{
+    broadcast::{Envelope, Peers, Relay},
+    limits::{Limits, NamespaceLimits},
+    metrics::{self, METRICS},
+    state,
+};
+
+/// Window in which the message rate is measured
+const RATE_WINDOW: Duration = Duration::from_secs(1);
+/// How often are the expired requests checked
+const EXPIRE_INTERVAL: Duration = Duration::from_secs(1);
+/// How long claims wait for objections of other instances
+const ARBITRATION_DELAY: Duration = Duration::from_millis(250);
+/// How often are worlds owned by clients of this instance announced to other instances
+const OWNER_ANNOUNCE_INTERVAL: Duration = Duration::from_secs(5);
+/// Owner on another instance is forgotten when it is not announced in time
+const REMOTE_OWNER_EXPIRY: Duration = Duration::from_secs(15);
+
+fn close_reason(code: ws::CloseCode, description: &str) -> ws::CloseReason {
+    ws::CloseReason {
+        code,
+        description: Some(description.to_string()),
+    }
+}
+
+fn claim_response(msg_id: Uuid, granted: bool) -> protocol::Message {
+    protocol::Message::Response(protocol::ResponseMessage::ClaimOwnership(
+        protocol::ClaimOwnershipResponse { msg_id, granted },
+    ))
+}
+
+/// Timeouts of websocket connections
+#[derive(Debug, Clone, Copy)]
+pub struct Timeouts {
+    /// How often heartbeat pings are sent
+    pub heartbeat_interval: Duration,
+    /// How long before lack of client response causes a timeout
+    pub client_timeout: Duration,
+    /// How long to wait for a response to a relayed request
+    pub request_timeout: Duration,
+    /// How long are worlds without clients kept
+    pub world_expiry: Duration,
+}
+
+impl Default for Timeouts {
+    fn default() -> Self {
+        Self {
+            heartbeat_interval: Duration::from_secs(5),
+            client_timeout: Duration::from_secs(10),
+            request_timeout: Duration::from_secs(5),
+            world_expiry: Duration::from_secs(7 * 24 * 60 * 60),
+        }
+    }
+}
+
+/// Identifies a world within its namespace and story
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct WorldKey {
+    pub namespace: String,
+    pub story: String,
+    pub world_id: Uuid,
+}
+
+impl fmt::Display for WorldKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}/{}", self.namespace, self.story, self.world_id)
+    }
+}
 
 /// Message which is sent to connected clients
 #[derive(Message)]
 #[rtype(result = "()")]
 pub struct Message(pub String);
 
+/// Closes the connection of a client
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct Close(pub Option<ws::CloseReason>);
+
 /// Messages which are sent between Connection and Manager actors
 /// New client is connected
 #[derive(Message)]
-#[rtype(usize)]
+#[rtype(result = "Result<Connected, ws::CloseReason>")]
 pub struct Connect {
-    pub world_id: Uuid,
+    pub key: WorldKey,
+    pub secret: Option<String>,
     pub addr: Recipient<Message>,
+    pub close: Recipient<Close>,
 }
 
+/// Client was registered by the manager
+#[derive(Debug)]
+pub struct Connected {
+    pub id: usize,
+    /// Limits of the namespace
+    pub limits: Limits,
+}
+
+/// Checks whether a client can join the world
+#[derive(Message)]
+#[rtype(result = "Access")]
+pub struct Authorize {
+    pub key: WorldKey,
+    pub secret: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    /// No secrets were registered for the world yet
+    /// (the first narrator which claims the world registers them - trust on first use)
+    Open,
+    Granted(protocol::Role),
+    Denied,
+}
+
+/// Ids of the worlds with connected clients
+#[derive(Message)]
+#[rtype(result = "Vec<Uuid>")]
+pub struct ListWorlds {
+    pub namespace: String,
+    pub story: String,
+}
+
+/// Events which were triggered in the world
+#[derive(Message)]
+#[rtype(result = "Vec<protocol::EventNotification>")]
+pub struct GetEvents {
+    pub key: WorldKey,
+}
+
+/// Passes a request to the owner of the world
+/// the response is sent back via the returned channel
+#[derive(Message)]
+#[rtype(result = "Option<oneshot::Receiver<protocol::Message>>")]
+pub struct ForwardRequest {
+    pub key: WorldKey,
+    pub request: protocol::RequestMessage,
+}
+
+/// Server is going down, all clients are disconnected
+/// and the state of the worlds is stored
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct Shutdown;
+
 /// Client is disconnected
 #[derive(Message)]
 #[rtype(result = "()")]
 pub struct Disconnect {
-    pub world_id: Uuid,
+    pub key: WorldKey,
     pub id: usize,
 }
 
@@ -39,57 +170,836 @@ pub struct Disconnect {
 #[derive(Message)]
 #[rtype(result = "()")]
 pub struct ClientMessage {
-    pub world_id: Uuid,
+    pub key: WorldKey,
+    /// Id of the sender connection
+    pub id: usize,
+    pub message: protocol::Message,
     pub data: String,
 }
 
-/// Should manage connected clients
+#[derive(Debug)]
+struct Client {
+    addr: Recipient<Message>,
+    close: Recipient<Close>,
+    connected: Instant,
+    secret: Option<String>,
+    /// `None` when the world has no secrets registered
+    role: Option<protocol::Role>,
+}
+
+/// Who is waiting for the response
+#[derive(Debug)]
+enum Requester {
+    /// Connection id of a client
+    Client(usize),
+    /// Request made via REST API
+    Channel(oneshot::Sender<protocol::Message>),
+    /// Request relayed from another instance to the local owner
+    Peer,
+}
+
+#[derive(Debug)]
+struct PendingRequest {
+    key: WorldKey,
+    requester: Requester,
+    created: Instant,
+}
+
+impl PendingRequest {
+    fn new(key: WorldKey, requester: Requester) -> Self {
+        Self {
+            key,
+            requester,
+            created: Instant::now(),
+        }
+    }
+
+    fn is_expired(&self, timeout: Duration) -> bool {
+        match &self.requester {
+            Requester::Channel(sender) if sender.is_closed() => true,
+            _ => self.created.elapsed() >= timeout,
+        }
+    }
+}
+
+/// Ownership transfer waiting for the answer of the candidate
+#[derive(Debug)]
+struct Transfer {
+    key: WorldKey,
+    owner: usize,
+    candidate: usize,
+    created: Instant,
+}
+
+/// Client which was asked to take over the world of a disconnected owner
+#[derive(Debug)]
+struct Election {
+    candidate: usize,
+    started: Instant,
+    /// Clients which were already asked (including the candidate)
+    asked: Vec<usize>,
+}
+
+/// Claim which waits for the objections of other instances
+#[derive(Debug)]
+struct PendingClaim {
+    id: usize,
+    request: protocol::ClaimOwnershipRequest,
+}
+
+/// State of a single world
 #[derive(Debug, Default)]
+struct World {
+    clients: HashMap<usize, Client>,
+    /// Connection which owns the world
+    owner: Option<usize>,
+    /// Instance whose client owns the world and when it was announced
+    remote_owner: Option<(Uuid, Instant)>,
+    /// Claim which is being arbitrated between instances
+    claim: Option<PendingClaim>,
+    /// Owner is being elected
+    election: Option<Election>,
+    /// Secrets registered by the owner
+    secrets: Option<protocol::JoinSecrets>,
+    /// Events which were relayed within the world
+    events: Vec<protocol::EventNotification>,
+    /// When the last client left the world
+    idle_since: Option<Instant>,
+}
+
+impl World {
+    /// Sends messages to all users in the world
+    fn send_message(&self, message: &str) {
+        self.clients.values().for_each(|client| {
+            debug!("sending_message; to={:?}", client.addr);
+            client.addr.do_send(Message(message.to_owned()));
+        });
+    }
+
+    /// Sends raw data to a single connection
+    fn send_data(&self, id: usize, data: &str) {
+        if let Some(client) = self.clients.get(&id) {
+            debug!("sending_message; to={:?}", client.addr);
+            client.addr.do_send(Message(data.to_owned()));
+        }
+    }
+
+    /// Sends request to the owner
+    /// (only the owner can respond so nobody gets it when the owner is not connected)
+    fn send_request(&self, sender: Option<usize>, data: &str) {
+        match self.owner {
+            Some(owner) if Some(owner) != sender => self.send_data(owner, data),
+            // owner handles its own requests
+            _ => {}
+        }
+    }
+
+    /// Sends message to a single connection
+    fn send_to(&self, id: usize, message: &protocol::Message) {
+        if let Some(client) = self.clients.get(&id) {
+            debug!("sending_message; to={:?}", client.addr);
+            client
+                .addr
+                .do_send(Message(serde_json::to_string(message).unwrap()));
+        }
+    }
+
+    /// Checks the secret against the secrets registered for the world
+    fn access(&self, secret: Option<&str>) -> Access {
+        if let Some(secrets) = self.secrets.as_ref() {
+            secret
+                .and_then(|secret| secrets.role(secret))
+                .map(Access::Granted)
+                .unwrap_or(Access::Denied)
+        } else {
+            Access::Open
+        }
+    }
+
+    fn role(&self, id: usize) -> Option<protocol::Role> {
+        self.clients.get(&id)?.role
+    }
+
+    /// Whether the client is allowed to own the world
+    fn is_narrator(&self, id: usize) -> bool {
+        if self.secrets.is_none() {
+            return true;
+        }
+        self.role(id) == Some(protocol::Role::Narrator)
+    }
+
+    /// Registers new secrets and disconnects clients which don't know them
+    /// (owner is `None` when the secrets were registered on another instance)
+    fn register_secrets(
+        &mut self,
+        key: &WorldKey,
+        owner: Option<usize>,
+        secrets: protocol::JoinSecrets,
+    ) {
+        if self.secrets.as_ref() == Some(&secrets) {
+            return;
+        }
+        info!("Secrets registered;world={},mid={:?}", key, owner);
+        for (id, client) in self.clients.iter_mut() {
+            client.role = if Some(*id) == owner {
+                Some(protocol::Role::Narrator)
+            } else {
+                client
+                    .secret
+                    .as_ref()
+                    .and_then(|secret| secrets.role(secret))
+            };
+            if client.role.is_none() {
+                debug!("Closing unauthorized;world={},mid={}", key, id);
+                client.close.do_send(Close(Some(close_reason(
+                    ws::CloseCode::Policy,
+                    "invalid secret",
+                ))));
+            }
+        }
+        self.secrets = Some(secrets);
+    }
+
+    /// Picks the longest connected client which can take over the world
+    fn candidate(&self, exclude: &[usize]) -> Option<usize> {
+        self.clients
+            .iter()
+            .filter(|(id, _)| !exclude.contains(id) && self.is_narrator(**id))
+            .min_by_key(|(_, client)| client.connected)
+            .map(|(id, _)| *id)
+    }
+
+    /// Elects a new owner when the owner of the world is disconnected
+    /// or asks the next candidate when the elected one is disconnected
+    fn elect(&mut self, key: &WorldKey, disconnected: usize) {
+        let asked = if self.owner == Some(disconnected) {
+            self.owner = None;
+            vec![]
+        } else if self.election.as_ref().map(|e| e.candidate) == Some(disconnected) {
+            self.election.take().unwrap().asked
+        } else {
+            return;
+        };
+        self.ask_candidate(key, asked);
+    }
+
+    /// Asks the next candidate to take over the world
+    /// the candidate becomes the owner once it claims the world
+    /// (a device without the world ignores the notification)
+    fn ask_candidate(&mut self, key: &WorldKey, mut asked: Vec<usize>) {
+        self.election = None;
+        if let Some(candidate) = self.candidate(&asked) {
+            info!("Owner elected;world={},mid={}", key, candidate);
+            self.send_to(
+                candidate,
+                &protocol::Message::Notification(protocol::NotificationMessage::Elected(
+                    protocol::ElectedNotification {
+                        world_id: key.world_id,
+                        secrets: self.secrets.clone(),
+                    },
+                )),
+            );
+            asked.push(candidate);
+            self.election = Some(Election {
+                candidate,
+                started: Instant::now(),
+                asked,
+            });
+        }
+    }
+
+    fn is_owned_by(&self, instance: Uuid) -> bool {
+        self.remote_owner.map(|(owner, _)| owner) == Some(instance)
+    }
+
+    /// Owner on another instance is gone, so clients of this instance can take over the world
+    fn forget_remote_owner(&mut self, key: &WorldKey) {
+        self.remote_owner = None;
+        if self.owner.is_none() && self.election.is_none() {
+            self.ask_candidate(key, vec![]);
+        }
+    }
+
+    /// Tries the next candidate when the elected one didn't claim the world in time
+    fn expire_election(&mut self, key: &WorldKey, timeout: Duration) {
+        if let Some(election) = self.election.as_ref() {
+            if election.started.elapsed() >= timeout {
+                warn!("election_timeout;world={},mid={}", key, election.candidate);
+                let asked = self.election.take().unwrap().asked;
+                self.ask_candidate(key, asked);
+            }
+        }
+    }
+
+    /// Keeps only the last `max_events` events
+    fn push_event(&mut self, event: protocol::EventNotification, max_events: usize) {
+        self.events.push(event);
+        self.trim_events(max_events);
+    }
+
+    fn trim_events(&mut self, max_events: usize) {
+        if self.events.len() > max_events {
+            let excess = self.events.len() - max_events;
+            self.events.drain(..excess);
+        }
+    }
+
+    /// Whether the world needs to be kept when no clients are connected
+    fn is_persistent(&self) -> bool {
+        self.secrets.is_some() || !self.events.is_empty()
+    }
+}
+
+/// Worlds of a namespace (story -> world_id -> world)
+type Stories = HashMap<String, HashMap<Uuid, World>>;
+
+/// Creates the world when it is not known yet
+fn world_entry<'a>(namespaces: &'a mut HashMap<String, Stories>, key: &WorldKey) -> &'a mut World {
+    namespaces
+        .entry(key.namespace.clone())
+        .or_default()
+        .entry(key.story.clone())
+        .or_default()
+        .entry(key.world_id)
+        .or_default()
+}
+
+/// Should manage connected clients
+#[derive(Debug)]
 pub struct WsManager {
-    clients: HashMap<Uuid, HashMap<usize, Recipient<Message>>>,
+    /// Worlds split by namespace and story
+    namespaces: HashMap<String, Stories>,
+    /// Ownership transfers in progress (msg_id -> transfer)
+    transfers: HashMap<Uuid, Transfer>,
+    /// Relayed requests waiting for the response (msg_id -> requester)
+    pending: HashMap<Uuid, PendingRequest>,
+    limits: NamespaceLimits,
+    timeouts: Timeouts,
+    /// Where secrets and events of the worlds are stored on shutdown
+    state_file: Option<PathBuf>,
+    /// New clients are refused during shutdown
+    shutting_down: bool,
     rng: ThreadRng,
+    /// Other server instances sharing the worlds
+    peers: Peers,
 }
 
 impl WsManager {
-    pub fn new() -> WsManager {
-        Self::default()
+    pub fn new(limits: NamespaceLimits, timeouts: Timeouts, peers: Peers) -> WsManager {
+        Self {
+            namespaces: HashMap::new(),
+            transfers: HashMap::new(),
+            pending: HashMap::new(),
+            limits,
+            timeouts,
+            state_file: None,
+            shutting_down: false,
+            rng: rand::thread_rng(),
+            peers,
+        }
+    }
+
+    /// Restores worlds which were stored during the last shutdown
+    pub fn with_state_file(mut self, path: PathBuf) -> anyhow::Result<Self> {
+        for stored in state::load(&path)? {
+            let max_events = self.max_events(&stored.namespace);
+            let world = self
+                .namespaces
+                .entry(stored.namespace)
+                .or_default()
+                .entry(stored.story)
+                .or_default()
+                .entry(stored.world_id)
+                .or_default();
+            world.secrets = stored.secrets;
+            world.events = stored.events;
+            world.trim_events(max_events);
+        }
+        info!("State loaded;path={:?}", path);
+        self.state_file = Some(path);
+        Ok(self)
     }
 }
 
 impl WsManager {
-    /// Sends messages to all users in the same world
-    fn send_message(&self, id: &Uuid, message: &str) {
-        debug!("client={:?}", self.clients.get(id));
-        self.clients.get(id).iter().for_each(|clients| {
-            clients.values().for_each(|addr| {
-                debug!("sending_message; to={:?}", addr);
-                addr.do_send(Message(message.to_owned()));
-            });
-        });
+    fn world(&self, key: &WorldKey) -> Option<&World> {
+        self.namespaces
+            .get(&key.namespace)?
+            .get(&key.story)?
+            .get(&key.world_id)
+    }
+
+    fn world_mut(&mut self, key: &WorldKey) -> Option<&mut World> {
+        self.namespaces
+            .get_mut(&key.namespace)?
+            .get_mut(&key.story)?
+            .get_mut(&key.world_id)
+    }
+
+    /// All worlds along with their keys
+    fn worlds_mut(&mut self) -> impl Iterator<Item = (WorldKey, &mut World)> {
+        self.namespaces.iter_mut().flat_map(|(namespace, stories)| {
+            stories.iter_mut().flat_map(move |(story, worlds)| {
+                worlds.iter_mut().map(move |(world_id, world)| {
+                    let key = WorldKey {
+                        namespace: namespace.clone(),
+                        story: story.clone(),
+                        world_id: *world_id,
+                    };
+                    (key, world)
+                })
+            })
+        })
+    }
+
+    fn worlds(&self) -> impl Iterator<Item = &World> {
+        self.namespaces
+            .values()
+            .flat_map(|stories| stories.values())
+            .flat_map(|worlds| worlds.values())
+    }
+
+    /// Events kept per world
+    /// (worlds of unknown namespaces may come from the state file or other instances)
+    fn max_events(&self, namespace: &str) -> usize {
+        self.limits
+            .get(namespace)
+            .unwrap_or(self.limits.default)
+            .max_events
+    }
+
+    /// Number of worlds with connected clients within the namespace
+    fn active_worlds(&self, namespace: &str) -> usize {
+        self.namespaces
+            .get(namespace)
+            .map(|stories| {
+                stories
+                    .values()
+                    .flat_map(|worlds| worlds.values())
+                    .filter(|world| !world.clients.is_empty())
+                    .count()
+            })
+            .unwrap_or(0)
+    }
+
+    fn access(&self, key: &WorldKey, secret: Option<&str>) -> Access {
+        self.world(key)
+            .map(|world| world.access(secret))
+            .unwrap_or(Access::Open)
+    }
+
+    fn update_metrics(&self) {
+        METRICS.set_connected_clients(self.worlds().map(|world| world.clients.len()).sum());
+        METRICS.set_active_worlds(
+            self.worlds()
+                .filter(|world| !world.clients.is_empty())
+                .count(),
+        );
+    }
+
+    /// Notifies requesters which didn't get the response in time
+    fn expire_requests(&mut self) {
+        let expired: Vec<Uuid> = self
+            .pending
+            .iter()
+            .filter(|(_, request)| request.is_expired(self.timeouts.request_timeout))
+            .map(|(msg_id, _)| *msg_id)
+            .collect();
+        for msg_id in expired {
+            let request = self.pending.remove(&msg_id).unwrap();
+            warn!("request_timeout;world={},msg_id={}", request.key, msg_id);
+            let error: protocol::Message =
+                protocol::ErrorResponse::new(Some(msg_id), protocol::ErrorCode::Timeout, None)
+                    .into();
+            match request.requester {
+                Requester::Client(id) => {
+                    if let Some(world) = self.world(&request.key) {
+                        world.send_to(id, &error);
+                    }
+                }
+                Requester::Channel(sender) => {
+                    let _ = sender.send(error);
+                }
+                // requester on the other instance gets its own timeout
+                Requester::Peer => {}
+            }
+        }
+    }
+
+    /// Notifies owners whose candidates didn't answer the transfer in time
+    fn expire_transfers(&mut self) {
+        let timeout = self.timeouts.request_timeout;
+        let expired: Vec<Uuid> = self
+            .transfers
+            .iter()
+            .filter(|(_, transfer)| transfer.created.elapsed() >= timeout)
+            .map(|(msg_id, _)| *msg_id)
+            .collect();
+        for msg_id in expired {
+            let transfer = self.transfers.remove(&msg_id).unwrap();
+            warn!("transfer_timeout;world={},msg_id={}", transfer.key, msg_id);
+            if let Some(world) = self.world(&transfer.key) {
+                world.send_to(
+                    transfer.owner,
+                    &protocol::ErrorResponse::new(Some(msg_id), protocol::ErrorCode::Timeout, None)
+                        .into(),
+                );
+            }
+        }
+    }
+
+    /// Forgets owners of other instances which are not announced anymore
+    fn expire_remote_owners(&mut self) {
+        let mut expired = vec![];
+        for (key, world) in self.worlds_mut() {
+            if matches!(world.remote_owner, Some((_, seen)) if seen.elapsed() >= REMOTE_OWNER_EXPIRY)
+            {
+                warn!("remote_owner_expired;world={}", key);
+                world.forget_remote_owner(&key);
+                expired.push(key);
+            }
+        }
+        for key in expired {
+            self.cleanup(&key);
+        }
+    }
+
+    /// Lets other instances know which worlds are owned by clients of this instance
+    fn announce_owners(&mut self) {
+        if !self.peers.has_peers() {
+            return;
+        }
+        let owned: Vec<WorldKey> = self
+            .worlds_mut()
+            .filter(|(_, world)| world.owner.is_some())
+            .map(|(key, _)| key)
+            .collect();
+        for key in owned {
+            self.peers.publish(&key, Relay::Owner);
+        }
+    }
+
+    /// Gives the world to the client and lets other instances know
+    fn grant_claim(&mut self, key: &WorldKey, id: usize, claim: protocol::ClaimOwnershipRequest) {
+        let world = if let Some(world) = self.world_mut(key) {
+            world
+        } else {
+            return;
+        };
+        info!("Owner claimed;world={},mid={}", key, id);
+        world.owner = Some(id);
+        world.remote_owner = None;
+        world.election = None;
+        if let Some(secrets) = claim.secrets.clone() {
+            world.register_secrets(key, Some(id), secrets);
+        }
+        world.send_to(id, &claim_response(claim.msg_id, true));
+        // secrets are accepted only from the instance of the owner
+        self.peers.publish(key, Relay::Owner);
+        if let Some(secrets) = claim.secrets {
+            self.peers.publish(key, Relay::Secrets { secrets });
+        }
+    }
+
+    /// Grants the claim when no other instance objected
+    fn finish_claim(&mut self, key: &WorldKey, msg_id: Uuid) {
+        let world = if let Some(world) = self.world_mut(key) {
+            world
+        } else {
+            return;
+        };
+        match world.claim.take() {
+            Some(claim) if claim.request.msg_id == msg_id => {
+                if world.owner.is_none()
+                    && world.remote_owner.is_none()
+                    && world.clients.contains_key(&claim.id)
+                {
+                    self.grant_claim(key, claim.id, claim.request);
+                } else {
+                    world.send_to(claim.id, &claim_response(msg_id, false));
+                }
+            }
+            other => world.claim = other,
+        }
+    }
+
+    fn expire_elections(&mut self) {
+        let timeout = self.timeouts.request_timeout;
+        for (key, world) in self.worlds_mut() {
+            world.expire_election(&key, timeout);
+        }
+    }
+
+    /// Forgets worlds (including their secrets and events) which had no clients for a long time
+    fn expire_worlds(&mut self) {
+        let expiry = self.timeouts.world_expiry;
+        let mut expired = vec![];
+        for (key, world) in self.worlds_mut() {
+            if !world.clients.is_empty() {
+                world.idle_since = None;
+            } else if world.idle_since.get_or_insert_with(Instant::now).elapsed() >= expiry {
+                expired.push(key);
+            }
+        }
+        for key in expired {
+            info!("World expired;world={}", key);
+            if let Some(worlds) = self
+                .namespaces
+                .get_mut(&key.namespace)
+                .and_then(|stories| stories.get_mut(&key.story))
+            {
+                worlds.remove(&key.world_id);
+            }
+            self.cleanup(&key);
+        }
+    }
+
+    fn save_state(&self) -> anyhow::Result<()> {
+        let path = if let Some(path) = self.state_file.as_ref() {
+            path
+        } else {
+            return Ok(());
+        };
+        let mut worlds = vec![];
+        for (namespace, stories) in &self.namespaces {
+            for (story, story_worlds) in stories {
+                for (world_id, world) in story_worlds {
+                    if world.is_persistent() {
+                        worlds.push(state::StoredWorld {
+                            namespace: namespace.clone(),
+                            story: story.clone(),
+                            world_id: *world_id,
+                            secrets: world.secrets.clone(),
+                            events: world.events.clone(),
+                        });
+                    }
+                }
+            }
+        }
+        state::save(path, &worlds)?;
+        info!("State saved;path={:?},worlds={}", path, worlds.len());
+        Ok(())
+    }
+
+    /// Removes the world when it is empty and there is nothing to keep
+    fn cleanup(&mut self, key: &WorldKey) {
+        if let Some(stories) = self.namespaces.get_mut(&key.namespace) {
+            if let Some(worlds) = stories.get_mut(&key.story) {
+                if worlds
+                    .get(&key.world_id)
+                    .map(|world| world.clients.is_empty() && !world.is_persistent())
+                    .unwrap_or(false)
+                {
+                    worlds.remove(&key.world_id);
+                }
+                if worlds.is_empty() {
+                    stories.remove(&key.story);
+                }
+            }
+            if stories.is_empty() {
+                self.namespaces.remove(&key.namespace);
+            }
+        }
     }
 }
 
 impl Actor for WsManager {
     type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        self.peers.subscribe(ctx.address().recipient());
+        ctx.run_interval(EXPIRE_INTERVAL, |act, _| {
+            act.expire_requests();
+            act.expire_transfers();
+            act.expire_elections();
+            act.expire_remote_owners();
+            act.expire_worlds();
+        });
+        ctx.run_interval(OWNER_ANNOUNCE_INTERVAL, |act, _| act.announce_owners());
+    }
 }
 
 /// Register a new client
 impl Handler<Connect> for WsManager {
-    type Result = usize;
+    type Result = MessageResult<Connect>;
 
     fn handle(&mut self, msg: Connect, _: &mut Context<Self>) -> Self::Result {
         debug!("Connect in Manager");
-        // register session with random id
+        if self.shutting_down {
+            return MessageResult(Err(close_reason(
+                ws::CloseCode::Restart,
+                "server restarting",
+            )));
+        }
+        let limits = if let Some(limits) = self.limits.get(&msg.key.namespace) {
+            limits
+        } else {
+            warn!("unknown_namespace;world={}", msg.key);
+            return MessageResult(Err(close_reason(
+                ws::CloseCode::Policy,
+                "unknown namespace",
+            )));
+        };
+
+        let active = self
+            .world(&msg.key)
+            .map(|world| !world.clients.is_empty())
+            .unwrap_or(false);
+        if !active
+            && (self.active_worlds(&msg.key.namespace) >= limits.max_worlds
+                || self
+                    .worlds()
+                    .filter(|world| !world.clients.is_empty())
+                    .count()
+                    >= self.limits.max_total_worlds)
+        {
+            warn!("too_many_worlds;world={}", msg.key);
+            return MessageResult(Err(close_reason(ws::CloseCode::Again, "too many worlds")));
+        }
+        if self
+            .worlds()
+            .map(|world| world.clients.len())
+            .sum::<usize>()
+            >= self.limits.max_total_clients
+        {
+            warn!("too_many_clients;world={}", msg.key);
+            return MessageResult(Err(close_reason(ws::CloseCode::Again, "too many clients")));
+        }
+
         let id = self.rng.gen::<usize>();
-        self.clients
-            .entry(msg.world_id)
-            .or_insert_with(HashMap::new)
-            .insert(id, msg.addr);
+        let world = self
+            .namespaces
+            .entry(msg.key.namespace.clone())
+            .or_default()
+            .entry(msg.key.story.clone())
+            .or_default()
+            .entry(msg.key.world_id)
+            .or_default();
+
+        if world.clients.len() >= limits.max_clients {
+            warn!("too_many_clients;world={}", msg.key);
+            let res = Err(close_reason(ws::CloseCode::Again, "too many clients"));
+            return MessageResult(res);
+        }
+
+        // secrets might have been registered after the client was authorized
+        let role = match world.access(msg.secret.as_deref()) {
+            Access::Open => None,
+            Access::Granted(role) => Some(role),
+            Access::Denied => {
+                let res = Err(close_reason(ws::CloseCode::Policy, "invalid secret"));
+                self.cleanup(&msg.key);
+                return MessageResult(res);
+            }
+        };
 
-        debug!("clients={:?}", self.clients);
+        // register session with random id
+        world.clients.insert(
+            id,
+            Client {
+                addr: msg.addr,
+                close: msg.close,
+                connected: Instant::now(),
+                secret: msg.secret,
+                role,
+            },
+        );
+        info!("Connected;world={},mid={}", msg.key, id);
+        self.update_metrics();
 
         // send id back
-        id
+        MessageResult(Ok(Connected { id, limits }))
+    }
+}
+
+/// Check the secret of a client before the connection is established
+impl Handler<Authorize> for WsManager {
+    type Result = MessageResult<Authorize>;
+
+    fn handle(&mut self, msg: Authorize, _: &mut Context<Self>) -> Self::Result {
+        MessageResult(self.access(&msg.key, msg.secret.as_deref()))
+    }
+}
+
+impl Handler<ListWorlds> for WsManager {
+    type Result = MessageResult<ListWorlds>;
+
+    fn handle(&mut self, msg: ListWorlds, _: &mut Context<Self>) -> Self::Result {
+        let mut worlds: Vec<Uuid> = self
+            .namespaces
+            .get(&msg.namespace)
+            .and_then(|stories| stories.get(&msg.story))
+            .map(|worlds| {
+                worlds
+                    .iter()
+                    .filter(|(_, world)| !world.clients.is_empty())
+                    .map(|(world_id, _)| *world_id)
+                    .collect()
+            })
+            .unwrap_or_default();
+        worlds.sort();
+        MessageResult(worlds)
+    }
+}
+
+impl Handler<GetEvents> for WsManager {
+    type Result = MessageResult<GetEvents>;
+
+    fn handle(&mut self, msg: GetEvents, _: &mut Context<Self>) -> Self::Result {
+        MessageResult(
+            self.world(&msg.key)
+                .map(|world| world.events.clone())
+                .unwrap_or_default(),
+        )
+    }
+}
+
+impl Handler<ForwardRequest> for WsManager {
+    type Result = MessageResult<ForwardRequest>;
+
+    fn handle(&mut self, msg: ForwardRequest, _: &mut Context<Self>) -> Self::Result {
+        let world = match self.world(&msg.key) {
+            Some(world) if !world.clients.is_empty() => world,
+            _ => return MessageResult(None),
+        };
+
+        let (sender, receiver) = oneshot::channel();
+        let msg_id = msg.request.msg_id();
+        let message = protocol::Message::Request(msg.request);
+        let data = serde_json::to_string(&message).unwrap();
+        world.send_request(None, &data);
+        if world.owner.is_none() {
+            // owner might be connected to another instance
+            self.peers.publish(&msg.key, Relay::Request { data });
+        }
+        self.pending.insert(
+            msg_id,
+            PendingRequest::new(msg.key, Requester::Channel(sender)),
+        );
+        MessageResult(Some(receiver))
+    }
+}
+
+impl Handler<Shutdown> for WsManager {
+    type Result = ();
+
+    fn handle(&mut self, _: Shutdown, _: &mut Context<Self>) {
+        info!("Shutting down manager");
+        self.shutting_down = true;
+        self.namespaces
+            .values()
+            .flat_map(|stories| stories.values())
+            .flat_map(|worlds| worlds.values())
+            .flat_map(|world| world.clients.values())
+            .for_each(|client| {
+                client.close.do_send(Close(Some(close_reason(
+                    ws::CloseCode::Restart,
+                    "server restarting",
+                ))));
+            });
+        if let Err(err) = self.save_state() {
+            error!("Failed to save state: {}", err);
+        }
     }
 }
 
@@ -98,26 +1008,385 @@ impl Handler<Disconnect> for WsManager {
     type Result = ();
 
     fn handle(&mut self, msg: Disconnect, _: &mut Context<Self>) {
-        info!("Disconnected;world={},mid={}", msg.world_id, msg.id);
-        if let Some(clients) = self.clients.get_mut(&msg.world_id) {
-            clients.remove(&msg.id);
-            if clients.is_empty() {
-                // Remove world if no clients are connected
-                self.clients.remove(&msg.world_id);
+        info!("Disconnected;world={},mid={}", msg.key, msg.id);
+        if let Some(world) = self.world_mut(&msg.key) {
+            world.clients.remove(&msg.id);
+            let released = world.owner == Some(msg.id);
+            world.elect(&msg.key, msg.id);
+            if released {
+                // clients of other instances can claim the world as well
+                self.peers.publish(&msg.key, Relay::Released);
+            }
+        }
+        // Nobody is waiting for the responses
+        self.pending.retain(|_, request| {
+            !(request.key == msg.key
+                && matches!(request.requester, Requester::Client(id) if id == msg.id))
+        });
+        // Transfers of the client can't be finished
+        let cancelled: Vec<Uuid> = self
+            .transfers
+            .iter()
+            .filter(|(_, transfer)| {
+                transfer.key == msg.key
+                    && (transfer.owner == msg.id || transfer.candidate == msg.id)
+            })
+            .map(|(msg_id, _)| *msg_id)
+            .collect();
+        for msg_id in cancelled {
+            let transfer = self.transfers.remove(&msg_id).unwrap();
+            if let Some(world) = self.world(&msg.key) {
+                world.send_to(
+                    transfer.owner,
+                    &protocol::ErrorResponse::new(
+                        Some(msg_id),
+                        protocol::ErrorCode::NoCandidate,
+                        None,
+                    )
+                    .into(),
+                );
+            }
+        }
+        // Remove world if no clients are connected
+        self.cleanup(&msg.key);
+        self.update_metrics();
+    }
+}
+
+/// A message from another server instance was recieved
+impl Handler<Envelope> for WsManager {
+    type Result = ();
+
+    fn handle(&mut self, msg: Envelope, _: &mut Context<Self>) {
+        if self.peers.is_own(&msg) {
+            return;
+        }
+        let key = msg.key;
+        let origin = msg.origin;
+        match msg.relay {
+            Relay::Broadcast { data } => {
+                let max_events = self.max_events(&key.namespace);
+                let world = if let Some(world) = self.world_mut(&key) {
+                    world
+                } else {
+                    return;
+                };
+                match serde_json::from_str(&data) {
+                    Ok(protocol::Message::Notification(protocol::NotificationMessage::Event(
+                        event,
+                    ))) if world.is_owned_by(origin) => world.push_event(event, max_events),
+                    Ok(protocol::Message::Notification(
+                        protocol::NotificationMessage::Event(_)
+                        | protocol::NotificationMessage::WorldUpdate,
+                    )) => {
+                        // Only the owner changes the world
+                        warn!("foreign_notification;world={},origin={}", key, origin);
+                        return;
+                    }
+                    _ => {}
+                }
+                world.send_message(&data);
+            }
+            Relay::Request { data } => {
+                let world = match self.world(&key) {
+                    Some(world) if world.owner.is_some() => world,
+                    // owner is connected elsewhere
+                    _ => return,
+                };
+                let msg_id = match serde_json::from_str(&data) {
+                    Ok(protocol::Message::Request(request)) => request.msg_id(),
+                    _ => return,
+                };
+                if self.pending.contains_key(&msg_id) {
+                    return;
+                }
+                world.send_request(None, &data);
+                // response of the owner is relayed back to the requesting instance
+                self.pending
+                    .insert(msg_id, PendingRequest::new(key, Requester::Peer));
+            }
+            Relay::Response { msg_id, data } => {
+                match self.pending.remove(&msg_id) {
+                    Some(request) if request.key == key => match request.requester {
+                        Requester::Client(id) => {
+                            if let Some(world) = self.world(&key) {
+                                world.send_data(id, &data);
+                            }
+                        }
+                        Requester::Channel(sender) => {
+                            if let Ok(message) = serde_json::from_str(&data) {
+                                let _ = sender.send(message);
+                            }
+                        }
+                        // both instances can't own the world
+                        Requester::Peer => {}
+                    },
+                    Some(request) => {
+                        self.pending.insert(msg_id, request);
+                    }
+                    // Request was made on another instance or it has already expired
+                    None => {}
+                }
+            }
+            Relay::Secrets { secrets } => {
+                // New clients of this instance need to know the secrets as well
+                let world = world_entry(&mut self.namespaces, &key);
+                if world.secrets.is_some() && !world.is_owned_by(origin) {
+                    // Only the owner can replace the secrets
+                    warn!("foreign_secrets;world={},origin={}", key, origin);
+                    return;
+                }
+                world.register_secrets(&key, None, secrets);
+            }
+            Relay::Claim => {
+                let instance = self.peers.instance();
+                let world = if let Some(world) = self.world_mut(&key) {
+                    world
+                } else {
+                    return;
+                };
+                if world.owner.is_some() {
+                    // the claiming instance refuses the claim once it knows the owner
+                    self.peers.publish(&key, Relay::Owner);
+                } else if world.claim.is_some() && origin < instance {
+                    // Concurrent claims are won by the instance with the lower id
+                    let claim = world.claim.take().unwrap();
+                    world.send_to(claim.id, &claim_response(claim.request.msg_id, false));
+                }
+            }
+            Relay::Owner => {
+                let instance = self.peers.instance();
+                let world = world_entry(&mut self.namespaces, &key);
+                if let Some(owner) = world.owner {
+                    if origin > instance {
+                        // Both instances granted the world, the lower id keeps it
+                        self.peers.publish(&key, Relay::Owner);
+                        return;
+                    }
+                    warn!("owner_conflict;world={},mid={}", key, owner);
+                    world.owner = None;
+                    world.send_to(
+                        owner,
+                        &protocol::ErrorResponse::new(None, protocol::ErrorCode::NotOwner, None)
+                            .into(),
+                    );
+                }
+                world.remote_owner = Some((origin, Instant::now()));
+                world.election = None;
+                if let Some(claim) = world.claim.take() {
+                    world.send_to(claim.id, &claim_response(claim.request.msg_id, false));
+                }
+            }
+            Relay::Released => {
+                let world = match self.world_mut(&key) {
+                    Some(world) if world.is_owned_by(origin) => world,
+                    _ => return,
+                };
+                world.forget_remote_owner(&key);
+                self.cleanup(&key);
             }
         }
     }
 }
 
 /// A message from client was recieved
-/// send it to all connected clients
-/// (including the one from which the message was recieved)
+/// notifications are sent to all connected clients,
+/// requests to the owner and responses only to the requester
 impl Handler<ClientMessage> for WsManager {
     type Result = ();
 
-    fn handle(&mut self, msg: ClientMessage, _: &mut Context<Self>) {
+    fn handle(&mut self, msg: ClientMessage, ctx: &mut Context<Self>) {
         debug!("Handling message");
-        self.send_message(&msg.world_id, &msg.data);
+        let key = msg.key;
+        let max_events = self.max_events(&key.namespace);
+        let world = if let Some(world) = self
+            .namespaces
+            .get_mut(&key.namespace)
+            .and_then(|e| e.get_mut(&key.story))
+            .and_then(|e| e.get_mut(&key.world_id))
+        {
+            world
+        } else {
+            return;
+        };
+
+        if world.role(msg.id) == Some(protocol::Role::Spectator) {
+            // Spectators can only watch the world
+            match msg.message {
+                protocol::Message::Request(protocol::RequestMessage::GetWorld(_)) => {}
+                protocol::Message::Request(request) => {
+                    warn!("spectator_request;world={},mid={}", key, msg.id);
+                    world.send_to(
+                        msg.id,
+                        &protocol::ErrorResponse::new(
+                            Some(request.msg_id()),
+                            protocol::ErrorCode::AccessDenied,
+                            None,
+                        )
+                        .into(),
+                    );
+                    return;
+                }
+                _ => {
+                    warn!("spectator_message;world={},mid={}", key, msg.id);
+                    return;
+                }
+            }
+        }
+
+        // Responses are routed only to the requester
+        if let protocol::Message::Response(response) = &msg.message {
+            if !matches!(response, protocol::ResponseMessage::TransferOwnership(_)) {
+                if world.owner != Some(msg.id) {
+                    // Requests are sent only to the owner
+                    warn!("unexpected_response;world={},mid={}", key, msg.id);
+                    return;
+                }
+                let msg_id = response.msg_id();
+                match msg_id.and_then(|msg_id| self.pending.remove(&msg_id)) {
+                    Some(request) if request.key == key => match request.requester {
+                        Requester::Client(id) => world.send_data(id, &msg.data),
+                        Requester::Channel(sender) => {
+                            let _ = sender.send(msg.message);
+                        }
+                        Requester::Peer => self.peers.publish(
+                            &key,
+                            Relay::Response {
+                                msg_id: msg_id.unwrap(),
+                                data: msg.data,
+                            },
+                        ),
+                    },
+                    Some(request) => {
+                        // Request belongs to a different world
+                        warn!("foreign_response;world={},mid={}", key, msg.id);
+                        self.pending.insert(msg_id.unwrap(), request);
+                    }
+                    // Nobody is waiting for the response (e.g. it has already expired)
+                    None => warn!("unexpected_response;world={},mid={}", key, msg.id),
+                }
+                return;
+            }
+        }
+
+        match msg.message {
+            protocol::Message::Notification(
+                protocol::NotificationMessage::Event(_)
+                | protocol::NotificationMessage::WorldUpdate,
+            ) if world.owner != Some(msg.id) => {
+                // Only the owner changes the world
+                warn!("not_owner_notification;world={},mid={}", key, msg.id);
+                world.send_to(
+                    msg.id,
+                    &protocol::ErrorResponse::new(None, protocol::ErrorCode::NotOwner, None).into(),
+                );
+            }
+            protocol::Message::Notification(protocol::NotificationMessage::Event(event)) => {
+                world.push_event(event, max_events);
+                world.send_message(&msg.data);
+                self.peers
+                    .publish(&key, Relay::Broadcast { data: msg.data });
+            }
+            protocol::Message::Request(protocol::RequestMessage::ClaimOwnership(claim)) => {
+                let refused = matches!(world.owner, Some(owner) if owner != msg.id)
+                    || !world.is_narrator(msg.id)
+                    // owned on another instance or claimed by another client
+                    || (world.owner.is_none()
+                        && (world.remote_owner.is_some() || world.claim.is_some()));
+                if refused {
+                    world.send_to(msg.id, &claim_response(claim.msg_id, false));
+                } else if world.owner.is_some() || !self.peers.has_peers() {
+                    self.grant_claim(&key, msg.id, claim);
+                } else {
+                    // Other instances can object until the claim is finished
+                    let msg_id = claim.msg_id;
+                    world.claim = Some(PendingClaim {
+                        id: msg.id,
+                        request: claim,
+                    });
+                    self.peers.publish(&key, Relay::Claim);
+                    ctx.run_later(ARBITRATION_DELAY, move |act, _| {
+                        act.finish_claim(&key, msg_id)
+                    });
+                }
+            }
+            protocol::Message::Request(protocol::RequestMessage::TransferOwnership(transfer)) => {
+                let error = if world.owner != Some(msg.id) {
+                    Some(protocol::ErrorCode::NotOwner)
+                } else if let Some(candidate) = world.candidate(&[msg.id]) {
+                    // Only the candidate gets the world
+                    self.transfers.insert(
+                        transfer.msg_id,
+                        Transfer {
+                            key: key.clone(),
+                            owner: msg.id,
+                            candidate,
+                            created: Instant::now(),
+                        },
+                    );
+                    if let Some(client) = world.clients.get(&candidate) {
+                        client.addr.do_send(Message(msg.data));
+                    }
+                    None
+                } else {
+                    Some(protocol::ErrorCode::NoCandidate)
+                };
+                if let Some(code) = error {
+                    world.send_to(
+                        msg.id,
+                        &protocol::ErrorResponse::new(Some(transfer.msg_id), code, None).into(),
+                    );
+                }
+            }
+            protocol::Message::Response(protocol::ResponseMessage::TransferOwnership(
+                transferred,
+            )) => {
+                match self.transfers.get(&transferred.msg_id) {
+                    // Only the candidate can answer the transfer
+                    Some(transfer) if transfer.candidate == msg.id && transfer.key == key => {
+                        self.transfers.remove(&transferred.msg_id);
+                        if transferred.accepted {
+                            info!("Owner transferred;world={},mid={}", key, msg.id);
+                            world.owner = Some(msg.id);
+                        }
+                        world.send_message(&msg.data);
+                        self.peers
+                            .publish(&key, Relay::Broadcast { data: msg.data });
+                    }
+                    _ => warn!("unexpected_response;world={},mid={}", key, msg.id),
+                }
+            }
+            protocol::Message::Request(request) => {
+                let msg_id = request.msg_id();
+                if self.pending.contains_key(&msg_id) {
+                    warn!("duplicate_request;world={},mid={}", key, msg.id);
+                    world.send_to(
+                        msg.id,
+                        &protocol::ErrorResponse::new(
+                            Some(msg_id),
+                            protocol::ErrorCode::InvalidMessage,
+                            Some("duplicate msg_id".to_string()),
+                        )
+                        .into(),
+                    );
+                    return;
+                }
+                self.pending.insert(
+                    msg_id,
+                    PendingRequest::new(key.clone(), Requester::Client(msg.id)),
+                );
+                world.send_request(Some(msg.id), &msg.data);
+                if world.owner.is_none() {
+                    // owner might be connected to another instance
+                    self.peers.publish(&key, Relay::Request { data: msg.data });
+                }
+            }
+            _ => {
+                world.send_message(&msg.data);
+                self.peers
+                    .publish(&key, Relay::Broadcast { data: msg.data });
+            }
+        }
     }
 }
 
@@ -128,8 +1397,17 @@ pub struct WsConnection {
     id: Option<usize>,
     /// Client must send ping
     hb: Instant,
-    /// Id of a world
-    world_id: Uuid,
+    /// World to which the connection belongs
+    key: WorldKey,
+    /// Secret used to join the world
+    secret: Option<String>,
+    /// Limits of the namespace (obtained from WsManager)
+    limits: Limits,
+    timeouts: Timeouts,
+    /// Start of the current rate window and number of messages within it
+    rate: (Instant, u32),
+    /// Why the connection was closed (used in metrics)
+    disconnect_reason: &'static str,
     /// Manager callback (e.g. to report disconnects)
     addr: Addr<WsManager>,
 }
@@ -148,15 +1426,25 @@ impl Actor for WsConnection {
         let addr = ctx.address();
         self.addr
             .send(Connect {
-                world_id: self.world_id,
-                addr: addr.recipient(),
+                key: self.key.clone(),
+                secret: self.secret.clone(),
+                addr: addr.clone().recipient(),
+                close: addr.recipient(),
             })
             .into_actor(self)
             .then(|res, act, ctx| {
                 match res {
-                    Ok(res) => act.id = Some(res),
+                    Ok(Ok(connected)) => {
+                        act.id = Some(connected.id);
+                        act.limits = connected.limits;
+                    }
+                    // rejected by the manager (e.g. quota exceeded)
+                    Ok(Err(reason)) => {
+                        warn!("connection_rejected;world={},reason={:?}", act.key, reason);
+                        act.disconnect(ctx, "rejected", Some(reason));
+                    }
                     // something is wrong with the server
-                    _ => ctx.stop(),
+                    _ => act.disconnect(ctx, "server_error", None),
                 }
                 fut::ready(())
             })
@@ -166,10 +1454,13 @@ impl Actor for WsConnection {
     fn stopping(&mut self, _: &mut Self::Context) -> Running {
         // notify server
         debug!("Stopping");
-        self.addr.do_send(Disconnect {
-            id: self.id.unwrap_or_default(),
-            world_id: self.world_id,
-        });
+        METRICS.disconnect(self.disconnect_reason);
+        if let Some(id) = self.id.take() {
+            self.addr.do_send(Disconnect {
+                id,
+                key: self.key.clone(),
+            });
+        }
         Running::Stop
     }
 }
@@ -183,12 +1474,29 @@ impl Handler<Message> for WsConnection {
     }
 }
 
+/// Connection is closed by Manager
+impl Handler<Close> for WsConnection {
+    type Result = ();
+
+    fn handle(&mut self, msg: Close, ctx: &mut Self::Context) {
+        ctx.close(msg.0);
+        self.disconnect(ctx, "closed_by_server", None);
+    }
+}
+
 /// WebSocket message handler
 impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for WsConnection {
     fn handle(&mut self, msg: Result<ws::Message, ws::ProtocolError>, ctx: &mut Self::Context) {
         let msg = match msg {
+            Err(ws::ProtocolError::Overflow) => {
+                // frame is bigger than `max_message_size`
+                warn!("message_too_big;world={}", self.key);
+                let reason = close_reason(ws::CloseCode::Size, "message too big");
+                self.disconnect(ctx, "message_too_big", Some(reason));
+                return;
+            }
             Err(_) => {
-                ctx.stop();
+                self.disconnect(ctx, "protocol_error", None);
                 return;
             }
             Ok(msg) => msg,
@@ -207,20 +1515,80 @@ impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for WsConnection {
                 self.hb = Instant::now();
             }
             ws::Message::Text(text) => {
+                if let Err((kind, reason)) = self.check_limits(text.len()) {
+                    warn!("limit_exceeded;world={},reason={:?}", self.key, reason);
+                    self.disconnect(ctx, kind, Some(reason));
+                    return;
+                }
+
+                let message = match serde_json::from_str::<protocol::Message>(&text) {
+                    Ok(message) => message,
+                    Err(err) => {
+                        // Reply only to the sender
+                        warn!("unparsable_message;world={},err={}", self.key, err);
+                        METRICS.message("unparsable");
+                        let error: protocol::Message = protocol::ErrorResponse::new(
+                            None,
+                            protocol::ErrorCode::ParseError,
+                            Some(err.to_string()),
+                        )
+                        .into();
+                        ctx.text(serde_json::to_string(&error).unwrap());
+                        return;
+                    }
+                };
+                if let Err(detail) = self.validate(&message) {
+                    warn!("invalid_message;world={},err={}", self.key, detail);
+                    METRICS.message("invalid");
+                    let msg_id = match &message {
+                        protocol::Message::Request(request) => Some(request.msg_id()),
+                        _ => None,
+                    };
+                    let error: protocol::Message = protocol::ErrorResponse::new(
+                        msg_id,
+                        protocol::ErrorCode::InvalidMessage,
+                        Some(detail.to_string()),
+                    )
+                    .into();
+                    ctx.text(serde_json::to_string(&error).unwrap());
+                    return;
+                }
+                METRICS.message(metrics::message_kind(&message));
+
                 // Forward message between clients
-                self.addr.do_send(ClientMessage {
-                    world_id: self.world_id,
-                    data: text.to_string(),
-                });
+                if let Some(id) = self.id {
+                    self.addr.do_send(ClientMessage {
+                        key: self.key.clone(),
+                        id,
+                        message,
+                        data: text.to_string(),
+                    });
+                }
             }
             ws::Message::Close(reason) => {
                 ctx.close(reason);
-                ctx.stop();
+                self.disconnect(ctx, "closed_by_client", None);
             }
             ws::Message::Continuation(_) => {
-                ctx.stop();
+                self.disconnect(ctx, "protocol_error", None);
+            }
+            ws::Message::Binary(data) => {
+                if let Err((kind, reason)) = self.check_limits(data.len()) {
+                    warn!("limit_exceeded;world={},reason={:?}", self.key, reason);
+                    self.disconnect(ctx, kind, Some(reason));
+                    return;
+                }
+                METRICS.message("binary");
+
+                // Only json text messages are relayed
+                let error: protocol::Message = protocol::ErrorResponse::new(
+                    None,
+                    protocol::ErrorCode::ParseError,
+                    Some("binary messages are not supported".to_string()),
+                )
+                .into();
+                ctx.text(serde_json::to_string(&error).unwrap());
             }
-            ws::Message::Binary(_) => (),
             ws::Message::Nop => (),
         }
     }
@@ -229,22 +1597,16 @@ impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for WsConnection {
 impl WsConnection {
     /// ping to client every second and also this method checks heartbeats from client
     fn hb(&self, ctx: &mut ws::WebsocketContext<Self>) {
-        let world_id = self.world_id;
-        ctx.run_interval(HEARTBEAT_INTERVAL, move |act, ctx| {
-            debug!("heartbeat;world={},act={:?}", world_id.clone(), act.id);
+        ctx.run_interval(self.timeouts.heartbeat_interval, move |act, ctx| {
+            debug!("heartbeat;world={},act={:?}", act.key, act.id);
             // check client heartbeats
-            if Instant::now().duration_since(act.hb) > CLIENT_TIMEOUT {
+            if Instant::now().duration_since(act.hb) > act.timeouts.client_timeout {
                 // heartbeat timed out
                 debug!("Websocket Client heartbeat failed, disconnecting!");
+                METRICS.heartbeat_timeout();
 
-                // notify chat server
-                act.addr.do_send(Disconnect {
-                    id: act.id.unwrap_or_default(),
-                    world_id,
-                });
-
-                // stop actor
-                ctx.stop();
+                // stop actor (manager is notified in `stopping`)
+                act.disconnect(ctx, "heartbeat_timeout", None);
 
                 // don't try to send a ping
                 return;
@@ -255,13 +1617,255 @@ impl WsConnection {
         });
     }
 
-    pub fn new(hb: Instant, world_id: Uuid, addr: Addr<WsManager>) -> Self {
-        debug!("new connection;world={}", world_id);
+    /// Closes the connection and remembers the reason for metrics
+    fn disconnect(
+        &mut self,
+        ctx: &mut ws::WebsocketContext<Self>,
+        kind: &'static str,
+        reason: Option<ws::CloseReason>,
+    ) {
+        self.disconnect_reason = kind;
+        if reason.is_some() {
+            ctx.close(reason);
+        }
+        ctx.stop();
+    }
+
+    /// Checks size of the incomming message and the message rate
+    fn check_limits(&mut self, size: usize) -> Result<(), (&'static str, ws::CloseReason)> {
+        if size > self.limits.max_message_size {
+            let reason = close_reason(ws::CloseCode::Size, "message too big");
+            return Err(("message_too_big", reason));
+        }
+
+        let now = Instant::now();
+        if now.duration_since(self.rate.0) >= RATE_WINDOW {
+            self.rate = (now, 0);
+        }
+        self.rate.1 += 1;
+        if self.rate.1 > self.limits.max_message_rate {
+            let reason = close_reason(ws::CloseCode::Policy, "rate limit exceeded");
+            return Err(("rate_limit", reason));
+        }
+        Ok(())
+    }
+
+    /// Checks whether the message can be relayed within the world
+    fn validate(&self, message: &protocol::Message) -> Result<(), &'static str> {
+        match message {
+            protocol::Message::Request(request) if request.world_id() != self.key.world_id => {
+                Err("request targets a different world")
+            }
+            protocol::Message::Notification(protocol::NotificationMessage::Elected(_)) => {
+                // Only the server elects owners
+                Err("elected notification can't be sent by clients")
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub fn new(
+        hb: Instant,
+        key: WorldKey,
+        secret: Option<String>,
+        timeouts: Timeouts,
+        addr: Addr<WsManager>,
+    ) -> Self {
+        debug!("new connection;world={}", key);
         Self {
             id: None,
             hb,
-            world_id,
+            key,
+            secret,
+            limits: Limits::default(),
+            timeouts,
+            rate: (hb, 0),
+            disconnect_reason: "connection_lost",
             addr,
         }
     }
 }
+
+#[cfg(test)]
+pub mod test {
+    use actix::{clock::sleep, prelude::*};
+    use pabitell_lib::protocol;
+    use std::{
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
+    use uuid::Uuid;
+
+    use super::{
+        ClientMessage, Close, Connect, Disconnect, Message, Timeouts, WorldKey, WsManager,
+        ARBITRATION_DELAY,
+    };
+    use crate::{
+        broadcast::{Envelope, MemoryBroadcast, Peers, Relay},
+        limits::NamespaceLimits,
+    };
+
+    /// Client which remembers what it got from the manager
+    #[derive(Debug, Clone, Default)]
+    struct Recorder {
+        messages: Arc<Mutex<Vec<protocol::Message>>>,
+        closed: Arc<Mutex<bool>>,
+    }
+
+    impl Actor for Recorder {
+        type Context = Context<Self>;
+    }
+
+    impl Handler<Message> for Recorder {
+        type Result = ();
+
+        fn handle(&mut self, msg: Message, _: &mut Self::Context) {
+            let message = serde_json::from_str(&msg.0).unwrap();
+            self.messages.lock().unwrap().push(message);
+        }
+    }
+
+    impl Handler<Close> for Recorder {
+        type Result = ();
+
+        fn handle(&mut self, _: Close, _: &mut Self::Context) {
+            *self.closed.lock().unwrap() = true;
+        }
+    }
+
+    impl Recorder {
+        fn claims(&self) -> Vec<bool> {
+            self.messages
+                .lock()
+                .unwrap()
+                .iter()
+                .filter_map(|message| match message {
+                    protocol::Message::Response(protocol::ResponseMessage::ClaimOwnership(
+                        claim,
+                    )) => Some(claim.granted),
+                    _ => None,
+                })
+                .collect()
+        }
+
+        fn is_elected(&self) -> bool {
+            self.messages.lock().unwrap().iter().any(|message| {
+                matches!(
+                    message,
+                    protocol::Message::Notification(protocol::NotificationMessage::Elected(_))
+                )
+            })
+        }
+    }
+
+    fn manager(bus: &MemoryBroadcast) -> Addr<WsManager> {
+        WsManager::new(
+            NamespaceLimits::default(),
+            Timeouts::default(),
+            Peers::new(Box::new(bus.clone())),
+        )
+        .start()
+    }
+
+    async fn connect(manager: &Addr<WsManager>, key: &WorldKey, secret: &str) -> (usize, Recorder) {
+        let recorder = Recorder::default();
+        let addr = recorder.clone().start();
+        let connected = manager
+            .send(Connect {
+                key: key.clone(),
+                secret: Some(secret.to_string()),
+                addr: addr.clone().recipient(),
+                close: addr.recipient(),
+            })
+            .await
+            .unwrap()
+            .unwrap();
+        (connected.id, recorder)
+    }
+
+    fn claim(key: &WorldKey, id: usize, secrets: Option<protocol::JoinSecrets>) -> ClientMessage {
+        let message = protocol::Message::Request(protocol::RequestMessage::ClaimOwnership(
+            protocol::ClaimOwnershipRequest {
+                msg_id: Uuid::new_v4(),
+                world_id: key.world_id,
+                secrets,
+            },
+        ));
+        ClientMessage {
+            key: key.clone(),
+            id,
+            data: serde_json::to_string(&message).unwrap(),
+            message,
+        }
+    }
+
+    fn world_key() -> WorldKey {
+        WorldKey {
+            namespace: "test".to_string(),
+            story: "doggie_and_kitie_cake".to_string(),
+            world_id: Uuid::new_v4(),
+        }
+    }
+
+    async fn settle() {
+        sleep(ARBITRATION_DELAY + Duration::from_millis(100)).await;
+    }
+
+    #[actix::test]
+    async fn ownership_between_instances() {
+        let bus = MemoryBroadcast::new();
+        let (first, second) = (manager(&bus), manager(&bus));
+        let key = world_key();
+        let secrets = protocol::JoinSecrets::generate();
+        let (first_id, first_client) = connect(&first, &key, &secrets.narrator).await;
+        let (second_id, second_client) = connect(&second, &key, &secrets.narrator).await;
+
+        first.do_send(claim(&key, first_id, Some(secrets.clone())));
+        settle().await;
+        assert_eq!(first_client.claims(), vec![true]);
+
+        // World is owned by a client of the other instance
+        second.do_send(claim(&key, second_id, None));
+        settle().await;
+        assert_eq!(second_client.claims(), vec![false]);
+
+        // Only the instance of the owner can replace the secrets
+        second.do_send(Envelope {
+            origin: Uuid::new_v4(),
+            key: key.clone(),
+            relay: Relay::Secrets {
+                secrets: protocol::JoinSecrets::generate(),
+            },
+        });
+        settle().await;
+        assert!(!*second_client.closed.lock().unwrap());
+
+        // Owner left, so the client of the other instance can take over
+        first.do_send(Disconnect {
+            key: key.clone(),
+            id: first_id,
+        });
+        settle().await;
+        assert!(second_client.is_elected());
+        second.do_send(claim(&key, second_id, None));
+        settle().await;
+        assert_eq!(second_client.claims(), vec![false, true]);
+    }
+
+    #[actix::test]
+    async fn concurrent_claims() {
+        let bus = MemoryBroadcast::new();
+        let (first, second) = (manager(&bus), manager(&bus));
+        let key = world_key();
+        let (first_id, first_client) = connect(&first, &key, "secret").await;
+        let (second_id, second_client) = connect(&second, &key, "secret").await;
+
+        first.do_send(claim(&key, first_id, None));
+        second.do_send(claim(&key, second_id, None));
+        settle().await;
+        let mut claims = first_client.claims();
+        claims.extend(second_client.claims());
+        claims.sort();
+        assert_eq!(claims, vec![false, true]);
+    }
+}